@@ -0,0 +1,141 @@
+// Timings of the compression pipeline, stage by stage, at several image sizes.
+// Each stage runs over every 8x8 block of the image, with the inputs prepared
+// up front so only that stage is measured. Throughput is in pixels, so the
+// numbers stay comparable across sizes.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_dct::bench::{self as stages, PIXEL_NORMALIZATION_OFFSET};
+use rust_dct::{compress_image_dct, CompressionConfig, Matrix, Quantizer};
+use std::hint::black_box;
+
+const SIZES: [usize; 3] = [64, 128, 256];
+
+// Smooth gradient with some texture, so quantization leaves a realistic mix
+// of zero and non-zero coefficients
+fn test_image(size: usize) -> Matrix {
+    (0..size)
+        .map(|y| (0..size).map(|x| ((x + y) * 255 / (2 * size) + (x * 7 + y * 13) % 23) as f64).collect())
+        .collect()
+}
+
+// Every block's matrices at each stage of the pipeline
+struct Blocks {
+    original: Vec<Matrix>,
+    normalized: Vec<Matrix>,
+    dct: Vec<Matrix>,
+    quantized: Vec<Matrix>,
+    reconstructed: Vec<Matrix>,
+}
+
+fn blocks(image: &Matrix, table: &Matrix, dct_matrix: &Matrix, dct_transposed: &Matrix) -> Blocks {
+    let original = stages::partition_into_blocks(image, table.len()).unwrap();
+    let normalized: Vec<Matrix> = original
+        .iter()
+        .map(|block| block.iter().map(|row| row.iter().map(|&x| x - PIXEL_NORMALIZATION_OFFSET).collect()).collect())
+        .collect();
+    let (dct, _) = stages::block_dct(image, table.len()).unwrap();
+    let quantized: Vec<Matrix> = dct
+        .iter()
+        .map(|block| stages::quantize_dct_matrix(block, table, Quantizer::Nearest).unwrap())
+        .collect();
+    let reconstructed = quantized
+        .iter()
+        .map(|block| stages::reconstruct_image_block(block, dct_transposed, dct_matrix).unwrap())
+        .collect();
+    Blocks { original, normalized, dct, quantized, reconstructed }
+}
+
+fn bench_stages(c: &mut Criterion) {
+    let table = stages::quantization_table();
+    let dct_matrix = stages::calculate_dct_coefficients(table.len()).unwrap();
+    let dct_transposed = stages::transpose(&dct_matrix).unwrap();
+
+    for size in SIZES {
+        let image = test_image(size);
+        let blocks = blocks(&image, &table, &dct_matrix, &dct_transposed);
+        let mut group = c.benchmark_group(format!("stages/{}x{}", size, size));
+        group.throughput(Throughput::Elements((size * size) as u64));
+
+        group.bench_function("partition", |b| {
+            b.iter(|| stages::partition_into_blocks(black_box(&image), table.len()).unwrap())
+        });
+        // Includes partitioning and level shifting, like the pipeline does
+        group.bench_function("forward_dct", |b| {
+            b.iter(|| stages::block_dct(black_box(&image), table.len()).unwrap())
+        });
+        group.bench_function("quantize", |b| {
+            b.iter(|| {
+                for block in &blocks.dct {
+                    black_box(stages::quantize_dct_matrix(block, &table, Quantizer::Nearest).unwrap());
+                }
+            })
+        });
+        group.bench_function("inverse_dct", |b| {
+            b.iter(|| {
+                for block in &blocks.quantized {
+                    black_box(stages::reconstruct_image_block(block, &dct_transposed, &dct_matrix).unwrap());
+                }
+            })
+        });
+        group.bench_function("explanation", |b| {
+            b.iter(|| {
+                for index in 0..blocks.original.len() {
+                    black_box(
+                        stages::generate_mathml_documentation(
+                            &blocks.original[index],
+                            &blocks.normalized[index],
+                            &blocks.dct[index],
+                            &table,
+                            &blocks.quantized[index],
+                            &blocks.reconstructed[index],
+                        )
+                        .unwrap(),
+                    );
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+fn bench_compression(c: &mut Criterion) {
+    let config = CompressionConfig::default();
+    let mut group = c.benchmark_group("compress_image_dct");
+    group.sample_size(10);
+    for size in SIZES {
+        let image = test_image(size);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &image, |b, image| {
+            b.iter(|| compress_image_dct(image.clone(), size, size, &config).unwrap())
+        });
+    }
+    group.finish();
+
+    // Per-block JSON export, i.e. `export_blocks_json` in the demo and
+    // `--export-blocks` in the CLI. The demo's own result goes through
+    // serde_wasm_bindgen, which only runs in a JS host
+    let mut group = c.benchmark_group("blocks_to_json");
+    group.sample_size(10);
+    for size in SIZES {
+        let result = compress_image_dct(test_image(size), size, size, &config).unwrap();
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &result, |b, result| {
+            b.iter(|| result.blocks_to_json().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_multiply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matrix_ops::multiply");
+    for size in [8, 16, 32] {
+        let left = stages::calculate_dct_coefficients(size).unwrap();
+        let right = test_image(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| stages::multiply(black_box(&left), black_box(&right)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stages, bench_compression, bench_multiply);
+criterion_main!(benches);
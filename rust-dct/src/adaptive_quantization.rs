@@ -0,0 +1,124 @@
+use crate::error_maps::Colormap;
+use crate::matrix_ops::{Matrix, MatrixError};
+use crate::rgba_image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+// Scales are kept within [1/MAX_SCALE, MAX_SCALE] of the base table
+pub const MAX_SCALE: f64 = 2.0;
+// Keeps perfectly flat blocks from pulling the ratio to zero
+const ACTIVITY_FLOOR: f64 = 1.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityMetric {
+    // Pixel variance of the block
+    #[default]
+    Variance,
+    // Mean absolute horizontal + vertical gradient inside the block
+    EdgeStrength,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveQuantization {
+    pub metric: ActivityMetric,
+    // Exponent on the activity ratio; 0 disables adaptation, 1 is proportional
+    pub strength: f64,
+}
+
+pub fn block_activity(block: &Matrix, metric: ActivityMetric) -> f64 {
+    let samples = block.iter().map(|row| row.len()).sum::<usize>() as f64;
+    match metric {
+        ActivityMetric::Variance => {
+            let mean = block.iter().flatten().sum::<f64>() / samples;
+            block.iter().flatten().map(|&value| (value - mean).powi(2)).sum::<f64>() / samples
+        }
+        ActivityMetric::EdgeStrength => {
+            let mut gradient = 0.0;
+            for (i, row) in block.iter().enumerate() {
+                for (j, &value) in row.iter().enumerate() {
+                    if j + 1 < row.len() {
+                        gradient += (row[j + 1] - value).abs();
+                    }
+                    if i + 1 < block.len() {
+                        gradient += (block[i + 1][j] - value).abs();
+                    }
+                }
+            }
+            gradient / samples
+        }
+    }
+}
+
+// Per-block multiplier of the quantization table: busy blocks (activity above
+// the image mean) get coarser steps, where the error is masked by texture,
+// and flat blocks get finer ones, where banding would show
+pub fn quantization_scales(blocks: &[Matrix], params: AdaptiveQuantization) -> Result<Vec<f64>, MatrixError> {
+    if !(params.strength.is_finite() && params.strength >= 0.0) {
+        return Err(MatrixError::InvalidParameter(
+            "Adaptive quantization strength must be a non-negative number".to_string(),
+        ));
+    }
+
+    let activities: Vec<f64> = blocks
+        .iter()
+        .map(|block| block_activity(block, params.metric) + ACTIVITY_FLOOR)
+        .collect();
+    // Geometric mean, so doubling and halving activity are symmetric
+    let mean_activity = (activities.iter().map(|a| a.ln()).sum::<f64>() / activities.len().max(1) as f64).exp();
+
+    Ok(activities
+        .iter()
+        .map(|&activity| {
+            (activity / mean_activity)
+                .powf(params.strength)
+                .clamp(1.0 / MAX_SCALE, MAX_SCALE)
+        })
+        .collect())
+}
+
+pub fn scale_quantization_table(table: &Matrix, scale: f64) -> Matrix {
+    table
+        .iter()
+        .map(|row| row.iter().map(|&step| (step * scale).max(1.0)).collect())
+        .collect()
+}
+
+// One pixel per block, log2(scale) mapped from [-log2 MAX_SCALE, +log2 MAX_SCALE] onto the colormap
+pub fn scale_map_image(
+    scales: &[f64],
+    blocks_x: usize,
+    blocks_y: usize,
+    colormap: Colormap,
+) -> RgbaImage {
+    let mut image = RgbaImage::new(blocks_x, blocks_y);
+    let range = MAX_SCALE.log2();
+    for (index, &scale) in scales.iter().enumerate().take(blocks_x * blocks_y) {
+        let t = (scale.log2() / range + 1.0) / 2.0;
+        image.set_pixel(index % blocks_x, index / blocks_x, colormap.color(t));
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busy_blocks_get_coarser_steps() {
+        let flat = vec![vec![120.0; 8]; 8];
+        let busy: Matrix = (0..8)
+            .map(|i| (0..8).map(|j| if (i + j) % 2 == 0 { 40.0 } else { 220.0 }).collect())
+            .collect();
+        let params = AdaptiveQuantization {
+            metric: ActivityMetric::EdgeStrength,
+            strength: 1.0,
+        };
+
+        let scales = quantization_scales(&[flat, busy], params).unwrap();
+
+        assert_eq!(scales, vec![1.0 / MAX_SCALE, MAX_SCALE]);
+        let map = scale_map_image(&scales, 2, 1, Colormap::Grayscale);
+        assert_eq!(&map.pixels[..4], &[0, 0, 0, 255]);
+        assert_eq!(&map.pixels[4..], &[255, 255, 255, 255]);
+    }
+}
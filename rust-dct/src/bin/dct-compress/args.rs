@@ -0,0 +1,215 @@
+use rust_dct::{EdgeMode, Quantizer, DEFAULT_QUALITY};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: dct-compress [OPTIONS] <INPUT>...
+
+Compresses PNG/PGM/PPM/PAM images with the DCT pipeline. For every input it
+writes <name>.dct.pgm (or .png; the reconstruction) and <name>.metrics.json
+to the output directory, so inputs need distinct names. Color input is
+converted to luma first.
+
+Options:
+  -q, --quality <1-100>    Scale the Annex K table like libjpeg [default: 50]
+  -b, --block-size <N>     Block size; the table is resampled for sizes other than 8 [default: 8]
+      --quantizer <MODE>   nearest, dead-zone[:WIDTH], rounding-offset[:OFFSET],
+                           truncate, floor or rate-distortion[:LAMBDA] [default: nearest]
+      --edge-mode <MODE>   crop, replicate, mirror or zero [default: replicate]
+  -o, --output-dir <DIR>   Where to write the results [default: .]
+      --output-format <FMT>
+                           pgm or png, for the reconstruction [default: pgm]
+      --jpeg               Also write <name>.jpg (baseline, 8x8 blocks only)
+      --dctz               Also write <name>.dctz (quantized coefficients, any settings)
+      --export-blocks      Also write every block's matrices to <name>.blocks.json and
+                           <name>.blocks.<original|dct|quantized|reconstructed>.csv
+      --npy                Also write NumPy arrays: <name>.blocks.<kind>.npy of shape
+                           (blocks_y, blocks_x, N, N) and <name>.reconstructed.npy
+  -h, --help               Print this help";
+
+const DEFAULT_DEAD_ZONE_WIDTH: f64 = 1.5;
+const DEFAULT_ROUNDING_OFFSET: f64 = 1.0 / 3.0;
+const DEFAULT_LAMBDA: f64 = 50.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Pgm,
+    Png,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub inputs: Vec<PathBuf>,
+    pub quality: u32,
+    pub block_size: usize,
+    pub quantizer: Quantizer,
+    pub edge_mode: EdgeMode,
+    pub output_dir: PathBuf,
+    pub output_format: OutputFormat,
+    pub jpeg: bool,
+    pub dctz: bool,
+    pub export_blocks: bool,
+    pub npy: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Args),
+    Help,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut parsed = Args {
+        inputs: Vec::new(),
+        quality: DEFAULT_QUALITY,
+        block_size: 8,
+        quantizer: Quantizer::default(),
+        edge_mode: EdgeMode::default(),
+        output_dir: PathBuf::from("."),
+        output_format: OutputFormat::default(),
+        jpeg: false,
+        dctz: false,
+        export_blocks: false,
+        npy: false,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-q" | "--quality" => parsed.quality = parse_number(&flag, &value()?)?,
+            "-b" | "--block-size" => parsed.block_size = parse_number(&flag, &value()?)?,
+            "--quantizer" => parsed.quantizer = parse_quantizer(&value()?)?,
+            "--edge-mode" => parsed.edge_mode = parse_edge_mode(&value()?)?,
+            "-o" | "--output-dir" => parsed.output_dir = PathBuf::from(value()?),
+            "--output-format" => parsed.output_format = parse_output_format(&value()?)?,
+            "--jpeg" => parsed.jpeg = true,
+            "--dctz" => parsed.dctz = true,
+            "--export-blocks" => parsed.export_blocks = true,
+            "--npy" => parsed.npy = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("Unknown option {}", arg)),
+            _ => parsed.inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if parsed.inputs.is_empty() {
+        return Err("No input images given".to_string());
+    }
+    if !(1..=100).contains(&parsed.quality) {
+        return Err("--quality must be between 1 and 100".to_string());
+    }
+    if parsed.block_size < 2 {
+        return Err("--block-size must be at least 2".to_string());
+    }
+    if parsed.jpeg && parsed.block_size != 8 {
+        return Err("--jpeg needs --block-size 8".to_string());
+    }
+    // Output names come from the file stem, so a/x.png and b/x.pgm would
+    // overwrite each other's results
+    let mut stems = HashMap::new();
+    for input in &parsed.inputs {
+        let stem = input
+            .file_stem()
+            .ok_or_else(|| format!("{} has no file name", input.display()))?;
+        if let Some(previous) = stems.insert(stem, input) {
+            return Err(format!(
+                "{} and {} would write the same output files",
+                previous.display(),
+                input.display()
+            ));
+        }
+    }
+    Ok(Command::Run(parsed))
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {:?}", flag, value))
+}
+
+fn parse_quantizer(value: &str) -> Result<Quantizer, String> {
+    let (mode, parameter) = match value.split_once(':') {
+        Some((mode, parameter)) => (mode, Some(parse_number::<f64>("--quantizer", parameter)?)),
+        None => (value, None),
+    };
+    let quantizer = match mode {
+        "nearest" => Quantizer::Nearest,
+        "dead-zone" => Quantizer::DeadZone {
+            width: parameter.unwrap_or(DEFAULT_DEAD_ZONE_WIDTH),
+        },
+        "rounding-offset" => Quantizer::RoundingOffset {
+            offset: parameter.unwrap_or(DEFAULT_ROUNDING_OFFSET),
+        },
+        "truncate" => Quantizer::Truncate,
+        "floor" => Quantizer::Floor,
+        "rate-distortion" => Quantizer::RateDistortion {
+            lambda: parameter.unwrap_or(DEFAULT_LAMBDA),
+        },
+        _ => return Err(format!("Unknown quantizer {:?}", mode)),
+    };
+    quantizer.validate()?;
+    Ok(quantizer)
+}
+
+fn parse_edge_mode(value: &str) -> Result<EdgeMode, String> {
+    match value {
+        "crop" => Ok(EdgeMode::Crop),
+        "replicate" => Ok(EdgeMode::Replicate),
+        "mirror" => Ok(EdgeMode::Mirror),
+        "zero" => Ok(EdgeMode::Zero),
+        _ => Err(format!("Unknown edge mode {:?}", value)),
+    }
+}
+
+fn parse_output_format(value: &str) -> Result<OutputFormat, String> {
+    match value {
+        "pgm" => Ok(OutputFormat::Pgm),
+        "png" => Ok(OutputFormat::Png),
+        _ => Err(format!("Unknown output format {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let command = parse_args(&[
+            "-q", "75", "--quantizer=dead-zone:2", "--edge-mode", "mirror", "--jpeg", "--output-format", "png", "a.pgm",
+            "b.ppm",
+        ])
+        .unwrap();
+
+        let Command::Run(args) = command else { panic!("expected Run") };
+        assert_eq!(args.quality, 75);
+        assert_eq!(args.quantizer, Quantizer::DeadZone { width: 2.0 });
+        assert_eq!(args.edge_mode, EdgeMode::Mirror);
+        assert_eq!(args.inputs, vec![PathBuf::from("a.pgm"), PathBuf::from("b.ppm")]);
+        assert!(args.jpeg);
+        assert_eq!(args.output_format, OutputFormat::Png);
+        assert_eq!(parse_args(&["--help"]).unwrap(), Command::Help);
+        assert!(parse_args(&["--jpeg", "-b", "16", "a.pgm"]).is_err());
+        assert!(parse_args(&["--quantizer", "bogus", "a.pgm"]).is_err());
+        assert!(parse_args(&["a/x.png", "b/x.png"]).is_err());
+        assert!(parse_args(&["x.pgm", "x.png"]).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+mod args;
+
+use args::{Args, Command, OutputFormat};
+use rust_dct::pnm::PnmFormat;
+use rust_dct::{dctz, jpeg_writer, png, pnm};
+use rust_dct::{BlockMatrixKind, EdgeMode, Encoder, EntropyCodedSize, Matrix, PlanarImage, QualityMetrics, Quantizer};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+#[derive(Serialize)]
+struct Metrics {
+    input: String,
+    width: usize,
+    height: usize,
+    block_size: usize,
+    quality: u32,
+    quantizer: Quantizer,
+    edge_mode: EdgeMode,
+    quantization_table: Matrix,
+    // Of the written (clamped) reconstruction against the input
+    quality_metrics: QualityMetrics,
+    entropy_coded_size: EntropyCodedSize,
+    bits_per_pixel: f64,
+    dct_zero_count: i32,
+    compressed_dct_zero_count: i32,
+    jpeg_bytes: Option<usize>,
+    dctz_bytes: Option<usize>,
+}
+
+fn main() -> ExitCode {
+    let args = match args::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            println!("{}", args::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, args::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    if let Err(error) = fs::create_dir_all(&args.output_dir) {
+        eprintln!("error: cannot create {}: {}", args.output_dir.display(), error);
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = false;
+    for input in &args.inputs {
+        match compress_file(input, &args) {
+            Ok(metrics) => println!(
+                "{}: PSNR {:.2} dB, SSIM {:.4}, {:.3} bits/pixel",
+                input.display(),
+                metrics.quality_metrics.psnr,
+                metrics.quality_metrics.ssim,
+                metrics.bits_per_pixel
+            ),
+            Err(error) => {
+                eprintln!("{}: {}", input.display(), error);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn compress_file(input: &Path, args: &Args) -> Result<Metrics, Box<dyn Error>> {
+    let image = PlanarImage::decode(&fs::read(input)?)?.to_luma();
+    let compressed = Encoder::new()
+        .quality(args.quality)
+        .block_size(args.block_size)
+        .quantizer(args.quantizer)
+        .edge_mode(args.edge_mode)
+        .encode(&image)?;
+    let result = &compressed.details;
+
+    let stem = input
+        .file_stem()
+        .ok_or("Input path has no file name")?
+        .to_string_lossy();
+    let reconstructed = PlanarImage::from_matrix(&compressed.reconstructed);
+    let (extension, encoded) = match args.output_format {
+        OutputFormat::Pgm => ("pgm", pnm::encode(&reconstructed, PnmFormat::RawPgm)?),
+        OutputFormat::Png => ("png", png::encode(&reconstructed)?),
+    };
+    fs::write(args.output_dir.join(format!("{}.dct.{}", stem, extension)), encoded)?;
+
+    let jpeg_bytes = if args.jpeg {
+        let jpeg = jpeg_writer::encode_grayscale_jpeg(
+            &result.original_image,
+            compressed.width,
+            compressed.height,
+            &result.quantization_table,
+            args.quantizer,
+        )?;
+        fs::write(args.output_dir.join(format!("{}.jpg", stem)), &jpeg)?;
+        Some(jpeg.len())
+    } else {
+        None
+    };
+
+    let dctz_bytes = if args.dctz {
+        let container = dctz::encode(&compressed)?;
+        fs::write(args.output_dir.join(format!("{}.dctz", stem)), &container)?;
+        Some(container.len())
+    } else {
+        None
+    };
+
+    if args.export_blocks {
+        fs::write(args.output_dir.join(format!("{}.blocks.json", stem)), result.blocks_to_json()?)?;
+        for kind in BlockMatrixKind::ALL {
+            fs::write(
+                args.output_dir.join(format!("{}.blocks.{}.csv", stem, kind.name())),
+                result.blocks_to_csv(kind),
+            )?;
+        }
+    }
+
+    if args.npy {
+        for kind in BlockMatrixKind::ALL {
+            fs::write(
+                args.output_dir.join(format!("{}.blocks.{}.npy", stem, kind.name())),
+                result.blocks_to_npy(kind),
+            )?;
+        }
+        fs::write(
+            args.output_dir.join(format!("{}.reconstructed.npy", stem)),
+            compressed.reconstruction_to_npy(),
+        )?;
+    }
+
+    let metrics = Metrics {
+        input: input.display().to_string(),
+        width: compressed.width,
+        height: compressed.height,
+        block_size: compressed.block_size,
+        quality: args.quality,
+        quantizer: args.quantizer,
+        edge_mode: args.edge_mode,
+        quantization_table: result.quantization_table.clone(),
+        quality_metrics: compressed.quality_metrics,
+        bits_per_pixel: compressed.bits_per_pixel(),
+        entropy_coded_size: result.entropy_coded_size,
+        dct_zero_count: result.dct_zero_count,
+        compressed_dct_zero_count: result.compressed_dct_zero_count,
+        jpeg_bytes,
+        dctz_bytes,
+    };
+    fs::write(
+        args.output_dir.join(format!("{}.metrics.json", stem)),
+        serde_json::to_string_pretty(&metrics)?,
+    )?;
+    Ok(metrics)
+}
@@ -0,0 +1,183 @@
+// Per-block matrices of a compression result as JSON, CSV or NumPy arrays, for
+// analysis in spreadsheets or Python. Blocks are indexed by block row/column;
+// rows and columns inside a block are pixel positions for the original and
+// reconstructed blocks and frequencies (v, u) for the DCT ones.
+use crate::dct_compression::CompressionResult;
+use crate::encoder::Compressed;
+use crate::matrix_ops::Matrix;
+use crate::npy;
+use serde::{Deserialize, Serialize};
+
+// Bumped whenever fields of the JSON document change meaning or go away
+pub const BLOCK_EXPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockMatrixKind {
+    Original,
+    Dct,
+    // Dequantized coefficients, i.e. level times step
+    Quantized,
+    Reconstructed,
+}
+
+impl BlockMatrixKind {
+    pub const ALL: [BlockMatrixKind; 4] = [
+        BlockMatrixKind::Original,
+        BlockMatrixKind::Dct,
+        BlockMatrixKind::Quantized,
+        BlockMatrixKind::Reconstructed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockMatrixKind::Original => "original",
+            BlockMatrixKind::Dct => "dct",
+            BlockMatrixKind::Quantized => "quantized",
+            BlockMatrixKind::Reconstructed => "reconstructed",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BlockExport<'a> {
+    schema_version: u32,
+    block_size: usize,
+    blocks_x: usize,
+    blocks_y: usize,
+    quantization_table: &'a Matrix,
+    blocks: Vec<ExportedBlock<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportedBlock<'a> {
+    block_row: usize,
+    block_col: usize,
+    quantization_scale: f64,
+    original: &'a Matrix,
+    dct: &'a Matrix,
+    quantized: &'a Matrix,
+    reconstructed: &'a Matrix,
+}
+
+impl CompressionResult {
+    pub fn block_size(&self) -> usize {
+        self.quantization_table.len()
+    }
+
+    // Whole blocks per row of the compressed image
+    pub fn blocks_x(&self) -> usize {
+        let width = self.original_image.first().map_or(0, |row| row.len());
+        width / self.block_size().max(1)
+    }
+
+    pub fn block_matrices(&self, kind: BlockMatrixKind) -> &[Matrix] {
+        match kind {
+            BlockMatrixKind::Original => &self.image_submatrices,
+            BlockMatrixKind::Dct => &self.dct_matrices,
+            BlockMatrixKind::Quantized => &self.compressed_dct_matrices,
+            BlockMatrixKind::Reconstructed => &self.compressed_image_submatrices,
+        }
+    }
+
+    // Every block with its four matrices, tagged with `schema_version`
+    pub fn blocks_to_json(&self) -> serde_json::Result<String> {
+        let blocks_x = self.blocks_x().max(1);
+        let blocks = (0..self.dct_matrices.len())
+            .map(|index| ExportedBlock {
+                block_row: index / blocks_x,
+                block_col: index % blocks_x,
+                quantization_scale: self.block_quantization_scales.get(index).copied().unwrap_or(1.0),
+                original: &self.image_submatrices[index],
+                dct: &self.dct_matrices[index],
+                quantized: &self.compressed_dct_matrices[index],
+                reconstructed: &self.compressed_image_submatrices[index],
+            })
+            .collect();
+        serde_json::to_string(&BlockExport {
+            schema_version: BLOCK_EXPORT_SCHEMA_VERSION,
+            block_size: self.block_size(),
+            blocks_x: self.blocks_x(),
+            blocks_y: self.dct_matrices.len() / blocks_x,
+            quantization_table: &self.quantization_table,
+            blocks,
+        })
+    }
+
+    // One line per coefficient: `block_row,block_col,row,col,value`
+    pub fn blocks_to_csv(&self, kind: BlockMatrixKind) -> String {
+        let blocks_x = self.blocks_x().max(1);
+        let mut csv = String::from("block_row,block_col,row,col,value\n");
+        for (index, block) in self.block_matrices(kind).iter().enumerate() {
+            for (row, values) in block.iter().enumerate() {
+                for (col, value) in values.iter().enumerate() {
+                    csv.push_str(&format!(
+                        "{},{},{},{},{}\n",
+                        index / blocks_x,
+                        index % blocks_x,
+                        row,
+                        col,
+                        value
+                    ));
+                }
+            }
+        }
+        csv
+    }
+
+    // `.npy` array of shape (blocks_y, blocks_x, N, N)
+    pub fn blocks_to_npy(&self, kind: BlockMatrixKind) -> Vec<u8> {
+        let blocks = self.block_matrices(kind);
+        let blocks_x = self.blocks_x().max(1);
+        let block_size = self.block_size();
+        npy::encode(
+            &[blocks.len() / blocks_x, blocks_x, block_size, block_size],
+            blocks.iter().flatten().flatten().copied(),
+        )
+    }
+}
+
+impl Compressed {
+    // `.npy` array of the reconstructed image, cropped and clamped as in `reconstructed`
+    pub fn reconstruction_to_npy(&self) -> Vec<u8> {
+        npy::encode_matrix(&self.reconstructed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Encoder;
+    use crate::matrix_ops;
+
+    #[test]
+    fn test_exports_index_blocks_by_row_and_column() {
+        let image = matrix_ops::test_image(24, 16);
+        let compressed = Encoder::new().encode(&image).unwrap();
+        let result = &compressed.details;
+
+        let json: serde_json::Value = serde_json::from_str(&result.blocks_to_json().unwrap()).unwrap();
+        assert_eq!(json["schema_version"], BLOCK_EXPORT_SCHEMA_VERSION);
+        assert_eq!((json["blocks_x"].as_u64(), json["blocks_y"].as_u64()), (Some(3), Some(2)));
+        let block = &json["blocks"][4];
+        assert_eq!((block["block_row"].as_u64(), block["block_col"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(block["original"][0][0].as_f64(), Some(image[8][8]));
+
+        let csv = result.blocks_to_csv(BlockMatrixKind::Original);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 6 * 64);
+        assert_eq!(lines[1 + 4 * 64 + 3], format!("1,1,0,3,{}", image[8][11]));
+
+        let npy = result.blocks_to_npy(BlockMatrixKind::Original);
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert!(String::from_utf8_lossy(&npy[10..10 + header_length]).contains("'shape': (2, 3, 8, 8)"));
+        // Block (1, 1), row 0, col 3
+        let offset = 10 + header_length + 8 * (((3 + 1) * 64) + 3);
+        assert_eq!(&npy[offset..offset + 8], &image[8][11].to_le_bytes());
+
+        let npy = compressed.reconstruction_to_npy();
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert!(String::from_utf8_lossy(&npy[10..10 + header_length]).contains("'shape': (16, 24)"));
+        assert_eq!(&npy[npy.len() - 8..], &compressed.reconstructed[15][23].to_le_bytes());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::dct_compression::{calculate_dct_coefficients, reconstruct_image_block};
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+
+// A mask can be sent either as on/off switches or as per-frequency weights
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CoefficientMask {
+    Binary(Vec<Vec<bool>>),
+    Weights(Matrix),
+}
+
+impl CoefficientMask {
+    pub fn to_weights(&self) -> Matrix {
+        match self {
+            CoefficientMask::Binary(switches) => switches
+                .iter()
+                .map(|row| row.iter().map(|&on| if on { 1.0 } else { 0.0 }).collect())
+                .collect(),
+            CoefficientMask::Weights(weights) => weights.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MaskedBlock {
+    pub block_index: usize,
+    pub masked_dct_matrix: Matrix,
+    pub reconstructed_block: Matrix,
+}
+
+pub fn apply_coefficient_mask(dct_matrix: &Matrix, weights: &Matrix) -> Result<Matrix, MatrixError> {
+    if dct_matrix.is_empty() || weights.is_empty() {
+        return Err(MatrixError::EmptyMatrix);
+    }
+
+    let same_shape = dct_matrix.len() == weights.len()
+        && dct_matrix
+            .iter()
+            .zip(weights.iter())
+            .all(|(dct_row, weight_row)| dct_row.len() == weight_row.len());
+
+    if !same_shape {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Coefficient mask must be {}x{} to match the DCT block",
+            dct_matrix.len(),
+            dct_matrix[0].len()
+        )));
+    }
+
+    Ok(dct_matrix
+        .iter()
+        .zip(weights.iter())
+        .map(|(dct_row, weight_row)| {
+            dct_row
+                .iter()
+                .zip(weight_row.iter())
+                .map(|(&coefficient, &weight)| coefficient * weight)
+                .collect()
+        })
+        .collect())
+}
+
+// Reconstructs either every block or only `block_index`, so the caller can
+// redraw just the affected part of the canvas
+pub fn reconstruct_masked_blocks(
+    dct_matrices: &[Matrix],
+    mask: &CoefficientMask,
+    block_index: Option<usize>,
+) -> Result<Vec<MaskedBlock>, MatrixError> {
+    let block_size = matrix_ops::square_block_size(dct_matrices)?;
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+    let weights = mask.to_weights();
+
+    let selected_indices: Vec<usize> = match block_index {
+        Some(index) if index >= dct_matrices.len() => {
+            return Err(MatrixError::BlockIndexOutOfRange {
+                index,
+                block_count: dct_matrices.len(),
+            })
+        }
+        Some(index) => vec![index],
+        None => (0..dct_matrices.len()).collect(),
+    };
+
+    selected_indices
+        .into_iter()
+        .map(|index| {
+            let masked_dct_matrix = apply_coefficient_mask(&dct_matrices[index], &weights)?;
+            let reconstructed_block = reconstruct_image_block(
+                &masked_dct_matrix,
+                &dct_coefficient_matrix_transposed,
+                &dct_coefficient_matrix,
+            )?;
+
+            Ok(MaskedBlock {
+                block_index: index,
+                masked_dct_matrix,
+                reconstructed_block,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dc_only_mask_produces_flat_block() {
+        let mut dct_block = vec![vec![3.0; 8]; 8];
+        dct_block[0][0] = 80.0;
+        let mut switches = vec![vec![false; 8]; 8];
+        switches[0][0] = true;
+
+        let blocks =
+            reconstruct_masked_blocks(&[dct_block], &CoefficientMask::Binary(switches), Some(0)).unwrap();
+
+        let reconstructed = &blocks[0].reconstructed_block;
+        assert!(reconstructed.iter().flatten().all(|&value| value == reconstructed[0][0]));
+        assert_eq!(reconstructed[0][0], 10.0 + 127.0);
+    }
+
+    #[test]
+    fn test_block_index_out_of_range() {
+        let mask = CoefficientMask::Weights(vec![vec![1.0; 8]; 8]);
+        let result = reconstruct_masked_blocks(&[vec![vec![0.0; 8]; 8]], &mask, Some(1));
+        assert!(matches!(result, Err(MatrixError::BlockIndexOutOfRange { index: 1, block_count: 1 })));
+    }
+
+    #[test]
+    fn test_rejects_empty_and_mismatched_blocks() {
+        let mask = CoefficientMask::Weights(vec![vec![1.0; 8]; 8]);
+        assert!(matches!(reconstruct_masked_blocks(&[vec![]], &mask, None), Err(MatrixError::EmptyMatrix)));
+        let blocks = [vec![vec![0.0; 8]; 8], vec![vec![0.0; 4]; 4]];
+        assert!(matches!(
+            reconstruct_masked_blocks(&blocks, &mask, Some(0)),
+            Err(MatrixError::IncompatibleDimensions(_))
+        ));
+    }
+}
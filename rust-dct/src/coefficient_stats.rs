@@ -0,0 +1,218 @@
+use crate::matrix_ops::{Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistogramBin {
+    pub level: i64,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct CoefficientStatistics {
+    pub block_count: usize,
+    // Per frequency position, over the unquantized DCT blocks
+    pub mean_absolute: Matrix,
+    pub variance: Matrix,
+    // Per frequency position, over the quantized levels (coefficient / step)
+    pub quantized_mean_absolute: Matrix,
+    pub quantized_variance: Matrix,
+    pub zero_probability: Matrix,
+    pub histograms: Vec<Vec<Vec<HistogramBin>>>,
+    pub position_entropy: Matrix,
+    // Shannon entropy of all quantized levels pooled together, in bits per symbol
+    pub entropy: f64,
+    pub estimated_total_bits: f64,
+}
+
+pub fn quantized_levels(quantized_dct: &Matrix, quantization_table: &Matrix) -> Vec<Vec<i64>> {
+    quantized_dct
+        .iter()
+        .zip(quantization_table.iter())
+        .map(|(row, steps)| {
+            row.iter()
+                .zip(steps.iter())
+                .map(|(&value, &step)| (value / step).round() as i64)
+                .collect()
+        })
+        .collect()
+}
+
+pub fn shannon_entropy<'a>(counts: impl Iterator<Item = &'a usize>, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    counts
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / total as f64;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+pub fn compute_coefficient_statistics(
+    dct_matrices: &[Matrix],
+    compressed_dct_matrices: &[Matrix],
+    quantization_tables: &[Matrix],
+) -> Result<CoefficientStatistics, MatrixError> {
+    if dct_matrices.len() != compressed_dct_matrices.len() || dct_matrices.len() != quantization_tables.len() {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Got {} DCT blocks, {} quantized blocks and {} quantization tables",
+            dct_matrices.len(),
+            compressed_dct_matrices.len(),
+            quantization_tables.len()
+        )));
+    }
+    // An image smaller than one block has nothing to measure
+    if dct_matrices.is_empty() {
+        return Ok(CoefficientStatistics::default());
+    }
+    let block_size = quantization_tables[0].len();
+    if block_size == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if dct_matrices
+        .iter()
+        .chain(compressed_dct_matrices.iter())
+        .chain(quantization_tables.iter())
+        .any(|block| block.len() != block_size || block.iter().any(|row| row.len() != block_size))
+    {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Every block must be {}x{} to match the quantization table",
+            block_size, block_size
+        )));
+    }
+
+    let levels: Vec<Vec<Vec<i64>>> = compressed_dct_matrices
+        .iter()
+        .zip(quantization_tables.iter())
+        .map(|(block, table)| quantized_levels(block, table))
+        .collect();
+
+    let level_matrices: Vec<Matrix> = levels
+        .iter()
+        .map(|block| block.iter().map(|row| row.iter().map(|&level| level as f64).collect()).collect())
+        .collect();
+
+    let (mean_absolute, variance) = position_moments(block_size, dct_matrices);
+    let (quantized_mean_absolute, quantized_variance) = position_moments(block_size, &level_matrices);
+
+    let block_count = dct_matrices.len();
+    let mut pooled_counts: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut zero_probability = vec![vec![0.0; block_size]; block_size];
+    let mut position_entropy = vec![vec![0.0; block_size]; block_size];
+    let mut histograms = Vec::with_capacity(block_size);
+
+    for i in 0..block_size {
+        let mut histogram_row = Vec::with_capacity(block_size);
+        for j in 0..block_size {
+            let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+            for block in &levels {
+                *counts.entry(block[i][j]).or_insert(0) += 1;
+                *pooled_counts.entry(block[i][j]).or_insert(0) += 1;
+            }
+
+            zero_probability[i][j] = *counts.get(&0).unwrap_or(&0) as f64 / block_count as f64;
+            position_entropy[i][j] = shannon_entropy(counts.values(), block_count);
+            histogram_row.push(
+                counts
+                    .into_iter()
+                    .map(|(level, count)| HistogramBin { level, count })
+                    .collect(),
+            );
+        }
+        histograms.push(histogram_row);
+    }
+
+    let symbol_count = block_count * block_size * block_size;
+    let entropy = shannon_entropy(pooled_counts.values(), symbol_count);
+
+    Ok(CoefficientStatistics {
+        block_count,
+        mean_absolute,
+        variance,
+        quantized_mean_absolute,
+        quantized_variance,
+        zero_probability,
+        histograms,
+        position_entropy,
+        entropy,
+        estimated_total_bits: entropy * symbol_count as f64,
+    })
+}
+
+// Mean absolute value and (population) variance of each frequency position
+fn position_moments(block_size: usize, blocks: &[Matrix]) -> (Matrix, Matrix) {
+    let count = blocks.len() as f64;
+    let mut sum = vec![vec![0.0; block_size]; block_size];
+    let mut sum_abs = vec![vec![0.0; block_size]; block_size];
+    let mut sum_squares = vec![vec![0.0; block_size]; block_size];
+
+    for block in blocks {
+        for i in 0..block_size {
+            for j in 0..block_size {
+                let value = block[i][j];
+                sum[i][j] += value;
+                sum_abs[i][j] += value.abs();
+                sum_squares[i][j] += value * value;
+            }
+        }
+    }
+
+    let mean_absolute = sum_abs
+        .iter()
+        .map(|row| row.iter().map(|&total| total / count).collect())
+        .collect();
+    let variance = sum_squares
+        .iter()
+        .zip(sum.iter())
+        .map(|(squares_row, sum_row)| {
+            squares_row
+                .iter()
+                .zip(sum_row.iter())
+                .map(|(&squares, &total)| {
+                    let mean = total / count;
+                    (squares / count - mean * mean).max(0.0)
+                })
+                .collect()
+        })
+        .collect();
+
+    (mean_absolute, variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics_over_two_blocks() {
+        let table = vec![vec![10.0; 2]; 2];
+        let dct = vec![vec![vec![20.0, -4.0], vec![0.0, 1.0]], vec![vec![40.0, 4.0], vec![0.0, -1.0]]];
+        let quantized = vec![vec![vec![20.0, 0.0], vec![0.0, 0.0]], vec![vec![40.0, 0.0], vec![0.0, 10.0]]];
+
+        let stats = compute_coefficient_statistics(&dct, &quantized, &[table.clone(), table]).unwrap();
+
+        assert_eq!(stats.mean_absolute[0][0], 30.0);
+        assert_eq!(stats.variance[0][0], 100.0);
+        assert_eq!(stats.mean_absolute[0][1], 4.0);
+        assert_eq!(stats.zero_probability[0][1], 1.0);
+        assert_eq!(stats.zero_probability[1][1], 0.5);
+        assert_eq!(
+            stats.histograms[0][0],
+            vec![HistogramBin { level: 2, count: 1 }, HistogramBin { level: 4, count: 1 }]
+        );
+        assert_eq!(stats.position_entropy[0][0], 1.0);
+        assert_eq!(stats.position_entropy[1][0], 0.0);
+
+        let empty = compute_coefficient_statistics(&[], &[], &[]).unwrap();
+        assert_eq!((empty.block_count, empty.entropy, empty.mean_absolute.len()), (0, 0.0, 0));
+    }
+
+    #[test]
+    fn test_shannon_entropy_of_uniform_symbols() {
+        let counts = [5usize, 5, 5, 5];
+        assert_eq!(shannon_entropy(counts.iter(), 20), 2.0);
+    }
+}
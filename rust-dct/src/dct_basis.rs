@@ -0,0 +1,91 @@
+use crate::dct_compression::calculate_dct_coefficients;
+use crate::matrix_ops::MatrixError;
+use crate::rgba_image::{gray_to_rgba, RgbaImage};
+
+// Width of the separator drawn between neighbouring basis tiles
+const TILE_GAP: usize = 1;
+const GAP_COLOR: [u8; 4] = [128, 128, 128, 255];
+// Largest width/height of the generated image (64 MiB of RGBA)
+const MAX_IMAGE_SIDE: usize = 4096;
+
+// Builds the N×N grid of 2-D DCT basis functions: tile (u, v) shows the
+// cosine pattern C[u][y] · C[v][x], scaled to black (-max) .. white (+max)
+pub fn generate_basis_image(block_size: usize, upscale: usize) -> Result<RgbaImage, MatrixError> {
+    if block_size == 0 || upscale == 0 {
+        return Err(MatrixError::InvalidParameter(
+            "Block size and upscale factor must be positive".to_string(),
+        ));
+    }
+
+    let image_size = block_size
+        .checked_mul(upscale)
+        .and_then(|tile_size| tile_size.checked_mul(block_size))
+        .and_then(|tiles| tiles.checked_add((block_size - 1) * TILE_GAP))
+        .filter(|&image_size| image_size <= MAX_IMAGE_SIDE)
+        .ok_or_else(|| {
+            MatrixError::InvalidParameter(format!(
+                "A {}x{} basis grid upscaled {} times is larger than {} pixels",
+                block_size, block_size, upscale, MAX_IMAGE_SIDE
+            ))
+        })?;
+    let tile_size = block_size * upscale;
+
+    let coefficients = calculate_dct_coefficients(block_size)?;
+    let mut image = RgbaImage::new(image_size, image_size);
+
+    for y in 0..image_size {
+        for x in 0..image_size {
+            image.set_pixel(x, y, GAP_COLOR);
+        }
+    }
+
+    for u in 0..block_size {
+        for v in 0..block_size {
+            let basis: Vec<Vec<f64>> = (0..block_size)
+                .map(|y| (0..block_size).map(|x| coefficients[u][y] * coefficients[v][x]).collect())
+                .collect();
+            let peak = basis
+                .iter()
+                .flatten()
+                .fold(0.0_f64, |max, &value| max.max(value.abs()));
+
+            let tile_top = u * (tile_size + TILE_GAP);
+            let tile_left = v * (tile_size + TILE_GAP);
+
+            for y in 0..tile_size {
+                for x in 0..tile_size {
+                    let value = basis[y / upscale][x / upscale] / peak;
+                    image.set_pixel(tile_left + x, tile_top + y, gray_to_rgba((value + 1.0) * 127.5));
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(image: &RgbaImage, x: usize, y: usize) -> &[u8] {
+        let offset = (y * image.width + x) * 4;
+        &image.pixels[offset..offset + 4]
+    }
+
+    #[test]
+    fn test_basis_image_layout() {
+        let image = generate_basis_image(8, 4).unwrap();
+        assert_eq!(image.width, 8 * 32 + 7);
+        assert_eq!(image.pixels.len(), image.width * image.height * 4);
+
+        // The DC tile is uniform and the gap column separates it from its neighbour
+        assert_eq!(pixel(&image, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&image, 31, 31), [255, 255, 255, 255]);
+        assert_eq!(pixel(&image, 32, 0), GAP_COLOR);
+
+        assert!(generate_basis_image(8, 64).is_err());
+        assert!(generate_basis_image(1 << 20, 1 << 20).is_err());
+        assert!(generate_basis_image(usize::MAX, 2).is_err());
+    }
+}
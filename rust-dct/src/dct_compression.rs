@@ -1,4 +1,20 @@
+use crate::adaptive_quantization::{self, AdaptiveQuantization};
+use crate::coefficient_stats::{self, CoefficientStatistics};
+use crate::deblocking::{self, DeblockingFilter, DeblockingResult};
+use crate::entropy_coding::{self, EntropyCodedSize, HuffmanCostModel};
+use crate::energy_compaction::{self, EnergyCompaction, DEFAULT_COMPACTION_COEFFICIENTS};
+use crate::error_maps::{self, Colormap, ErrorMaps};
 use crate::matrix_ops::{self, Matrix, MatrixError};
+use crate::parallel;
+use crate::perceptual_table::{self, ViewingConditions};
+use crate::pocs::{self, PocsResult, PocsSmoothing};
+use crate::quality_metrics::{self, QualityMetrics};
+use crate::rgba_image::RgbaImage;
+use crate::quality_scaling;
+use crate::quantizer::Quantizer;
+use crate::region_of_interest::{self, RegionOfInterest, RegionOfInterestReport};
+use crate::simd;
+use crate::trellis::{self, TrellisReport};
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
@@ -12,7 +28,123 @@ pub struct CompressionResult {
     pub compressed_dct_zero_count: i32,
     pub image_submatrices: Vec<Matrix>,
     pub compressed_image_submatrices: Vec<Matrix>,
+    // Empty unless `CompressionConfig::explain_blocks` is set
     pub latex_calculations: Vec<String>,
+    pub error_maps: ErrorMaps,
+    pub quantization_table: Matrix,
+    pub coefficient_statistics: CoefficientStatistics,
+    pub energy_compaction: EnergyCompaction,
+    // Measured over the area covered by whole blocks
+    pub quality_metrics: QualityMetrics,
+    pub deblocking: Option<DeblockingResult>,
+    pub pocs: Option<PocsResult>,
+    pub quantizer: Quantizer,
+    pub entropy_coded_size: EntropyCodedSize,
+    // Empty unless `CompressionConfig::compare_quantizers` is set
+    pub quantizer_comparison: Vec<QuantizerReport>,
+    // Per-block decisions when `Quantizer::RateDistortion` is selected
+    pub trellis: Option<TrellisReport>,
+    // Multiplier applied to `quantization_table` for each block (all 1 without adaptation)
+    pub block_quantization_scales: Vec<f64>,
+    pub quantization_scale_map: RgbaImage,
+    pub region_of_interest: Option<RegionOfInterestReport>,
+    // Generated table against Annex K when a non-default table is selected
+    pub table_comparison: Option<TableComparison>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QuantizerReport {
+    pub quantizer: Quantizer,
+    pub zero_count: i32,
+    pub entropy_coded_size: EntropyCodedSize,
+    pub psnr: f64,
+}
+
+// Both tables applied with the configured quantizer to the same image; each
+// is measured over the area covered by its own block size
+#[derive(Serialize, Deserialize)]
+pub struct TableComparison {
+    pub annex_k: QuantizerReport,
+    pub selected: QuantizerReport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum QuantizationTableSource {
+    // The Annex K luminance table (quality 50), 8x8
+    #[default]
+    AnnexK,
+    // Annex K scaled to a libjpeg quality (1–100) and resampled to the block size
+    AnnexKScaled { quality: u32, block_size: usize },
+    // Derived from a contrast sensitivity model; the block size follows the conditions
+    Perceptual(ViewingConditions),
+    // Any square table, e.g. the output of the table optimizer
+    Custom { table: Matrix },
+}
+
+impl QuantizationTableSource {
+    pub fn table(&self) -> Result<Matrix, MatrixError> {
+        match *self {
+            QuantizationTableSource::AnnexK => Ok(quantization_table()),
+            QuantizationTableSource::AnnexKScaled { quality, block_size } => {
+                quality_scaling::scale_table(&quality_scaling::resample_table(&quantization_table(), block_size)?, quality)
+            }
+            QuantizationTableSource::Perceptual(conditions) => perceptual_table::generate_perceptual_table(conditions),
+            QuantizationTableSource::Custom { ref table } => {
+                let size = table.len();
+                if size == 0 || table.iter().any(|row| row.len() != size) {
+                    return Err(MatrixError::IncompatibleDimensions(
+                        "Custom quantization table must be square".to_string(),
+                    ));
+                }
+                if table.iter().flatten().any(|&step| !(step.is_finite() && step >= 1.0)) {
+                    return Err(MatrixError::InvalidParameter(
+                        "Quantization steps must be at least 1".to_string(),
+                    ));
+                }
+                Ok(table.clone())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    pub colormap: Colormap,
+    // K in the "energy captured by the first K zigzag coefficients" report
+    pub compaction_coefficients: usize,
+    pub deblocking: Option<DeblockingFilter>,
+    // Iterative artifact reduction constrained to the quantization cells
+    pub pocs: Option<PocsSmoothing>,
+    pub quantizer: Quantizer,
+    pub adaptive_quantization: Option<AdaptiveQuantization>,
+    // Combined multiplicatively with the adaptive quantization scales
+    pub region_of_interest: Option<RegionOfInterest>,
+    // Also sets the block size
+    pub quantization_table: QuantizationTableSource,
+    // Re-encode every block with each rule of `Quantizer::comparison_set` for
+    // `quantizer_comparison`; off by default since it multiplies the work
+    pub compare_quantizers: bool,
+    // MathML walkthrough of every block for `latex_calculations`; off by
+    // default since it takes longer than the compression itself
+    pub explain_blocks: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::default(),
+            compaction_coefficients: DEFAULT_COMPACTION_COEFFICIENTS,
+            deblocking: None,
+            pocs: None,
+            quantizer: Quantizer::default(),
+            adaptive_quantization: None,
+            region_of_interest: None,
+            quantization_table: QuantizationTableSource::default(),
+            compare_quantizers: false,
+            explain_blocks: false,
+        }
+    }
 }
 
 // Standard JPEG quantization matrix for quality level 50
@@ -27,83 +159,458 @@ const QUANTIZATION_MATRIX: [[f64; 8]; 8] = [
     [72.0, 92.0, 95.0, 98.0, 112.0, 100.0, 103.0, 99.0],
 ];
 
-const BLOCK_SIZE: usize = 8;
-const PIXEL_NORMALIZATION_OFFSET: f64 = 127.0;
+pub const PIXEL_NORMALIZATION_OFFSET: f64 = 127.0;
+
+pub fn quantization_table() -> Matrix {
+    QUANTIZATION_MATRIX.iter().map(|row| row.to_vec()).collect()
+}
 
 pub fn compress_image_dct(
     image: Matrix,
     width: usize,
     height: usize,
+    config: &CompressionConfig,
 ) -> Result<CompressionResult, MatrixError> {
-    let dct_coefficient_matrix = calculate_dct_coefficients(BLOCK_SIZE)?;
-    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+    let mut job = CompressionJob::new(image, width, height, config.clone())?;
+    while !job.progress().done {
+        job.step(job.total_blocks())?;
+    }
+    job.finish()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CompressionProgress {
+    pub processed_blocks: usize,
+    pub total_blocks: usize,
+    // Whole-image stages, run one per `step` once every block is done
+    pub completed_stages: usize,
+    pub total_stages: usize,
+    pub next_stage: Option<AnalysisStage>,
+    // All blocks and stages are done; `finish` assembles the result
+    pub done: bool,
+}
+
+// Whole-image work after the last block. Only the stages the configuration
+// asks for are run
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+    // Error maps and quality metrics
+    Quality,
+    // Coefficient statistics, energy compaction, entropy-coded size and the
+    // trellis and region-of-interest reports
+    Statistics,
+    Deblocking,
+    Pocs,
+    QuantizerComparison,
+    TableComparison,
+}
+
+// Outputs of the stages run so far
+#[derive(Default)]
+struct Analysis {
+    error_maps: Option<ErrorMaps>,
+    quality_metrics: Option<QualityMetrics>,
+    coefficient_statistics: Option<CoefficientStatistics>,
+    energy_compaction: Option<EnergyCompaction>,
+    entropy_coded_size: Option<EntropyCodedSize>,
+    trellis: Option<TrellisReport>,
+    region_of_interest: Option<RegionOfInterestReport>,
+    deblocking: Option<DeblockingResult>,
+    pocs: Option<PocsResult>,
+    quantizer_comparison: Vec<QuantizerReport>,
+    table_comparison: Option<TableComparison>,
+}
+
+// `compress_image_dct` split into steps of a few blocks, so a caller (e.g. a
+// Web Worker) can report progress in between and cancel by dropping the job
+pub struct CompressionJob {
+    image: Matrix,
+    width: usize,
+    height: usize,
+    config: CompressionConfig,
+    quantization_table: Matrix,
+    block_size: usize,
+    dct_coefficient_matrix: Matrix,
+    dct_coefficient_matrix_transposed: Matrix,
+    image_submatrices: Vec<Matrix>,
+    block_quantization_scales: Vec<f64>,
+    block_quantization_tables: Vec<Matrix>,
+    block_importance: Option<Vec<f64>>,
+    compressed_image: Matrix,
+    dct_matrices: Vec<Matrix>,
+    compressed_dct_matrices: Vec<Matrix>,
+    dct_zero_count: i32,
+    compressed_dct_zero_count: i32,
+    compressed_image_submatrices: Vec<Matrix>,
+    latex_calculations: Vec<String>,
+    stages: Vec<AnalysisStage>,
+    completed_stages: usize,
+    analysis: Analysis,
+}
+
+impl CompressionJob {
+    // Validates the configuration and derives every block's quantization table
+    pub fn new(image: Matrix, width: usize, height: usize, config: CompressionConfig) -> Result<Self, MatrixError> {
+        config.quantizer.validate().map_err(MatrixError::InvalidParameter)?;
+        let quantization_table = config.quantization_table.table()?;
+        let block_size = quantization_table.len();
+        let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+        let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+
+        // Partition the image into blocks of the table's size (8x8 for Annex K)
+        let image_submatrices = matrix_ops::partition_into_blocks(&image, block_size)?;
+
+        let mut block_quantization_scales = match config.adaptive_quantization {
+            Some(params) => adaptive_quantization::quantization_scales(&image_submatrices, params)?,
+            None => vec![1.0; image_submatrices.len()],
+        };
+        let block_importance = match &config.region_of_interest {
+            Some(roi) => {
+                let importance = roi.block_importance(width, height, block_size)?;
+                for (scale, roi_scale) in block_quantization_scales.iter_mut().zip(roi.quantization_scales(&importance)?) {
+                    *scale *= roi_scale;
+                }
+                Some(importance)
+            }
+            None => None,
+        };
+        let block_quantization_tables: Vec<Matrix> = block_quantization_scales
+            .iter()
+            .map(|&scale| adaptive_quantization::scale_quantization_table(&quantization_table, scale))
+            .collect();
+
+        let mut stages = vec![AnalysisStage::Quality, AnalysisStage::Statistics];
+        if config.deblocking.is_some() {
+            stages.push(AnalysisStage::Deblocking);
+        }
+        if config.pocs.is_some() {
+            stages.push(AnalysisStage::Pocs);
+        }
+        if config.compare_quantizers {
+            stages.push(AnalysisStage::QuantizerComparison);
+        }
+        if config.quantization_table != QuantizationTableSource::AnnexK {
+            stages.push(AnalysisStage::TableComparison);
+        }
+
+        let block_count = image_submatrices.len();
+        Ok(Self {
+            image,
+            width,
+            height,
+            config,
+            quantization_table,
+            block_size,
+            dct_coefficient_matrix,
+            dct_coefficient_matrix_transposed,
+            image_submatrices,
+            block_quantization_scales,
+            block_quantization_tables,
+            block_importance,
+            compressed_image: vec![vec![0.0; width]; height],
+            dct_matrices: Vec::with_capacity(block_count),
+            compressed_dct_matrices: Vec::with_capacity(block_count),
+            dct_zero_count: 0,
+            compressed_dct_zero_count: 0,
+            compressed_image_submatrices: Vec::with_capacity(block_count),
+            latex_calculations: Vec::new(),
+            stages,
+            completed_stages: 0,
+            analysis: Analysis::default(),
+        })
+    }
+
+    pub fn total_blocks(&self) -> usize {
+        self.image_submatrices.len()
+    }
 
-    let mut compressed_image = vec![vec![0.0; width]; height];
-    let mut dct_matrices = Vec::new();
-    let mut compressed_dct_matrices = Vec::new();
-    let mut dct_zero_count = 0;
-    let mut compressed_dct_zero_count = 0;
-    let mut compressed_image_submatrices = Vec::new();
-    let mut latex_calculations = Vec::new();
+    pub fn progress(&self) -> CompressionProgress {
+        let blocks_done = self.dct_matrices.len() == self.total_blocks();
+        CompressionProgress {
+            processed_blocks: self.dct_matrices.len(),
+            total_blocks: self.total_blocks(),
+            completed_stages: self.completed_stages,
+            total_stages: self.stages.len(),
+            next_stage: self.stages.get(self.completed_stages).copied(),
+            done: blocks_done && self.completed_stages == self.stages.len(),
+        }
+    }
+
+    // Transforms, quantizes and reconstructs up to `block_count` more blocks.
+    // Once every block is done, each call runs the next whole-image stage instead
+    pub fn step(&mut self, block_count: usize) -> Result<CompressionProgress, MatrixError> {
+        if self.dct_matrices.len() < self.total_blocks() {
+            self.step_blocks(block_count)?;
+        } else if let Some(&stage) = self.stages.get(self.completed_stages) {
+            self.run_stage(stage)?;
+            self.completed_stages += 1;
+        }
+        Ok(self.progress())
+    }
 
-    // Partition the image into 8x8 blocks
-    let image_submatrices = matrix_ops::partition_into_blocks(&image, BLOCK_SIZE)?;
+    fn step_blocks(&mut self, block_count: usize) -> Result<(), MatrixError> {
+        let start = self.dct_matrices.len();
+        let end = (start + block_count).min(self.total_blocks());
+        // Blocks are independent; only the merge into the image is sequential
+        let processed = parallel::map_blocks(start..end, |block_index| self.process_block(block_index))
+            .into_iter()
+            .collect::<Result<Vec<_>, MatrixError>>()?;
 
-    for submatrix in &image_submatrices {
+        for (block_index, (dct_matrix, quantized_dct, reconstructed_matrix, latex)) in (start..end).zip(processed) {
+            self.latex_calculations.extend(latex);
+            matrix_ops::merge_blocks(&mut self.compressed_image, &reconstructed_matrix, block_index, self.block_size)?;
+
+            update_compression_results(
+                &mut self.dct_matrices,
+                &mut self.compressed_dct_matrices,
+                &mut self.dct_zero_count,
+                &mut self.compressed_dct_zero_count,
+                &mut self.compressed_image_submatrices,
+                dct_matrix,
+                quantized_dct,
+                reconstructed_matrix,
+            );
+        }
+        Ok(())
+    }
+
+    // DCT, quantized DCT, reconstruction and, if enabled, MathML walkthrough of one block
+    fn process_block(&self, block_index: usize) -> Result<(Matrix, Matrix, Matrix, Option<String>), MatrixError> {
+        let submatrix = &self.image_submatrices[block_index];
+        let block_table = &self.block_quantization_tables[block_index];
         let normalized_matrix = normalize_pixel_values(submatrix)?;
 
         // Calculate DCT using matrix chain multiplication
-        let dct_matrix = matrix_ops::multiply_chain(&[
-            &dct_coefficient_matrix,
+        let dct_matrix = transform_block(
+            &self.dct_coefficient_matrix,
             &normalized_matrix,
-            &dct_coefficient_matrix_transposed,
-        ])?;
+            &self.dct_coefficient_matrix_transposed,
+        )?;
 
-        let quantized_dct = quantize_dct_matrix(&dct_matrix)?;
+        let quantized_dct = quantize_dct_matrix(&dct_matrix, block_table, self.config.quantizer)?;
         let reconstructed_matrix = reconstruct_image_block(
             &quantized_dct,
-            &dct_coefficient_matrix_transposed,
-            &dct_coefficient_matrix,
+            &self.dct_coefficient_matrix_transposed,
+            &self.dct_coefficient_matrix,
         )?;
 
-        latex_calculations.push(generate_mathml_documentation(
-            submatrix,
-            &normalized_matrix,
-            &dct_matrix,
-            &quantized_dct,
-            &reconstructed_matrix,
-        )?);
-
-        update_compression_results(
-            &mut dct_matrices,
-            &mut compressed_dct_matrices,
-            &mut dct_zero_count,
-            &mut compressed_dct_zero_count,
-            &mut compressed_image_submatrices,
-            dct_matrix,
-            quantized_dct,
-            reconstructed_matrix.clone(),
+        let latex = if self.config.explain_blocks {
+            Some(generate_mathml_documentation(
+                submatrix,
+                &normalized_matrix,
+                &dct_matrix,
+                block_table,
+                &quantized_dct,
+                &reconstructed_matrix,
+            )?)
+        } else {
+            None
+        };
+        Ok((dct_matrix, quantized_dct, reconstructed_matrix, latex))
+    }
+
+    // The original over the area covered by whole blocks, which is all the
+    // reconstruction can be compared against
+    fn covered_original(&self) -> Matrix {
+        let covered_height = self.height / self.block_size * self.block_size;
+        let covered_width = self.width / self.block_size * self.block_size;
+        matrix_ops::crop(&self.image, covered_height, covered_width)
+    }
+
+    fn run_stage(&mut self, stage: AnalysisStage) -> Result<(), MatrixError> {
+        let config = &self.config;
+        match stage {
+            AnalysisStage::Quality => {
+                self.analysis.error_maps = Some(error_maps::compute_error_maps(
+                    &self.image,
+                    &self.compressed_image,
+                    self.block_size,
+                    config.colormap,
+                )?);
+                self.analysis.quality_metrics =
+                    Some(measure_covered_quality(&self.covered_original(), &self.compressed_image)?);
+            }
+            AnalysisStage::Statistics => {
+                self.analysis.coefficient_statistics = Some(coefficient_stats::compute_coefficient_statistics(
+                    &self.dct_matrices,
+                    &self.compressed_dct_matrices,
+                    &self.block_quantization_tables,
+                )?);
+                self.analysis.energy_compaction = Some(energy_compaction::compute_energy_compaction(
+                    &self.dct_matrices,
+                    config.compaction_coefficients,
+                )?);
+                self.analysis.entropy_coded_size = Some(entropy_coding::entropy_coded_size(&block_levels(
+                    &self.compressed_dct_matrices,
+                    &self.block_quantization_tables,
+                )));
+                self.analysis.trellis = match config.quantizer {
+                    Quantizer::RateDistortion { lambda } => Some(trellis::trellis_report(
+                        &self.dct_matrices,
+                        &self.compressed_dct_matrices,
+                        &self.block_quantization_tables,
+                        lambda,
+                        &HuffmanCostModel::standard(),
+                    )),
+                    _ => None,
+                };
+                self.analysis.region_of_interest = match self.block_importance.take() {
+                    Some(importance) => Some(region_of_interest::region_report(
+                        importance,
+                        &self.image_submatrices,
+                        &self.compressed_image_submatrices,
+                    )?),
+                    None => None,
+                };
+            }
+            AnalysisStage::Deblocking => {
+                if let Some(filter) = config.deblocking {
+                    let before = self.analysis.quality_metrics.ok_or_else(|| missing_stage(AnalysisStage::Quality))?;
+                    let filtered_image = deblocking::deblock(
+                        &self.compressed_image,
+                        self.block_size,
+                        self.quantization_table[0][0],
+                        &self.block_quantization_scales,
+                        filter,
+                    )?;
+                    let after = measure_covered_quality(&self.covered_original(), &filtered_image)?;
+                    self.analysis.deblocking = Some(DeblockingResult {
+                        strength: filter.strength,
+                        filtered_image,
+                        before,
+                        after,
+                    });
+                }
+            }
+            AnalysisStage::Pocs => {
+                if let Some(params) = config.pocs {
+                    let reconstructed_image = pocs::pocs_reconstruct(
+                        &self.compressed_dct_matrices,
+                        &self.block_quantization_tables,
+                        config.quantizer,
+                        self.width,
+                        self.height,
+                        params,
+                    )?;
+                    let quality_metrics = measure_covered_quality(&self.covered_original(), &reconstructed_image)?;
+                    self.analysis.pocs = Some(PocsResult {
+                        iterations: params.iterations,
+                        smoothing: params.smoothing,
+                        reconstructed_image,
+                        quality_metrics,
+                    });
+                }
+            }
+            // An image without whole blocks has nothing to compare
+            AnalysisStage::QuantizerComparison | AnalysisStage::TableComparison if self.dct_matrices.is_empty() => {}
+            AnalysisStage::QuantizerComparison => {
+                let mut compared_quantizers = Quantizer::comparison_set();
+                if !compared_quantizers.contains(&config.quantizer) {
+                    compared_quantizers.push(config.quantizer);
+                }
+                let covered_original = self.covered_original();
+                self.analysis.quantizer_comparison = compared_quantizers
+                    .into_iter()
+                    .map(|quantizer| {
+                        evaluate_quantizer(
+                            &self.dct_matrices,
+                            &self.block_quantization_tables,
+                            &covered_original,
+                            quantizer,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+            }
+            AnalysisStage::TableComparison => {
+                self.analysis.table_comparison = Some(TableComparison {
+                    annex_k: evaluate_table(&self.image, &quantization_table(), config.quantizer)?,
+                    selected: evaluate_table(&self.image, &self.quantization_table, config.quantizer)?,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Assembles the result once every block and stage is done
+    pub fn finish(self) -> Result<CompressionResult, MatrixError> {
+        let progress = self.progress();
+        if !progress.done {
+            return Err(MatrixError::InvalidParameter(format!(
+                "Compression finished after {} of {} blocks and {} of {} stages",
+                progress.processed_blocks, progress.total_blocks, progress.completed_stages, progress.total_stages
+            )));
+        }
+        let CompressionJob {
+            image,
+            width,
+            height,
+            config,
+            quantization_table,
+            block_size,
+            image_submatrices,
+            block_quantization_scales,
+            compressed_image,
+            dct_matrices,
+            compressed_dct_matrices,
+            dct_zero_count,
+            compressed_dct_zero_count,
+            compressed_image_submatrices,
+            latex_calculations,
+            analysis,
+            ..
+        } = self;
+
+        let quantization_scale_map = adaptive_quantization::scale_map_image(
+            &block_quantization_scales,
+            width / block_size,
+            height / block_size,
+            config.colormap,
         );
 
-        matrix_ops::merge_blocks(
-            &mut compressed_image,
-            &reconstructed_matrix,
-            dct_matrices.len() - 1,
-            BLOCK_SIZE,
-        )?;
+        Ok(CompressionResult {
+            original_image: image,
+            compressed_image,
+            dct_matrices,
+            compressed_dct_matrices,
+            dct_zero_count,
+            compressed_dct_zero_count,
+            image_submatrices,
+            compressed_image_submatrices,
+            latex_calculations,
+            error_maps: analysis.error_maps.ok_or_else(|| missing_stage(AnalysisStage::Quality))?,
+            quantization_table,
+            coefficient_statistics: analysis
+                .coefficient_statistics
+                .ok_or_else(|| missing_stage(AnalysisStage::Statistics))?,
+            energy_compaction: analysis.energy_compaction.ok_or_else(|| missing_stage(AnalysisStage::Statistics))?,
+            quality_metrics: analysis.quality_metrics.ok_or_else(|| missing_stage(AnalysisStage::Quality))?,
+            deblocking: analysis.deblocking,
+            pocs: analysis.pocs,
+            quantizer: config.quantizer,
+            entropy_coded_size: analysis.entropy_coded_size.ok_or_else(|| missing_stage(AnalysisStage::Statistics))?,
+            quantizer_comparison: analysis.quantizer_comparison,
+            trellis: analysis.trellis,
+            block_quantization_scales,
+            quantization_scale_map,
+            region_of_interest: analysis.region_of_interest,
+            table_comparison: analysis.table_comparison,
+        })
     }
+}
 
-    Ok(CompressionResult {
-        original_image: image,
-        compressed_image,
-        dct_matrices,
-        compressed_dct_matrices,
-        dct_zero_count,
-        compressed_dct_zero_count,
-        image_submatrices,
-        compressed_image_submatrices,
-        latex_calculations,
-    })
+fn missing_stage(stage: AnalysisStage) -> MatrixError {
+    MatrixError::InvalidParameter(format!("{:?} stage has not run", stage))
+}
+
+// Quality over the area covered by whole blocks. An image smaller than one
+// block has no compressed area, which counts as unchanged
+fn measure_covered_quality(covered_original: &Matrix, reconstructed: &Matrix) -> Result<QualityMetrics, MatrixError> {
+    let (height, width) = (covered_original.len(), covered_original.first().map_or(0, |row| row.len()));
+    if height == 0 || width == 0 {
+        return Ok(QualityMetrics { mse: 0.0, psnr: f64::INFINITY, ssim: 1.0 });
+    }
+    quality_metrics::measure_quality(covered_original, &matrix_ops::crop(reconstructed, height, width))
 }
 
 fn normalize_pixel_values(matrix: &Matrix) -> Result<Matrix, MatrixError> {
@@ -117,30 +624,161 @@ fn normalize_pixel_values(matrix: &Matrix) -> Result<Matrix, MatrixError> {
         .collect())
 }
 
-fn quantize_dct_matrix(dct_matrix: &Matrix) -> Result<Matrix, MatrixError> {
-    if dct_matrix.len() != BLOCK_SIZE || dct_matrix[0].len() != BLOCK_SIZE {
+// Quantizes the whole image with one table (no per-block scaling), using
+// blocks of the table's size
+pub fn evaluate_table(image: &Matrix, table: &Matrix, quantizer: Quantizer) -> Result<QuantizerReport, MatrixError> {
+    let (dct_matrices, covered_original) = block_dct(image, table.len())?;
+    evaluate_quantizer(&dct_matrices, &vec![table.clone(); dct_matrices.len()], &covered_original, quantizer)
+}
+
+// DCT of every whole block of the image, and the area those blocks cover
+pub fn block_dct(image: &Matrix, block_size: usize) -> Result<(Vec<Matrix>, Matrix), MatrixError> {
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+
+    let dct_matrices = matrix_ops::partition_into_blocks(image, block_size)?
+        .iter()
+        .map(|block| {
+            transform_block(
+                &dct_coefficient_matrix,
+                &normalize_pixel_values(block)?,
+                &dct_coefficient_matrix_transposed,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let width = image.first().map(|row| row.len()).unwrap_or(0);
+    let covered_original = matrix_ops::crop(
+        image,
+        image.len() / block_size * block_size,
+        width / block_size * block_size,
+    );
+    Ok((dct_matrices, covered_original))
+}
+
+// Integer levels of every block, each divided by its own quantization table
+pub fn block_levels(quantized_dct_matrices: &[Matrix], quantization_tables: &[Matrix]) -> Vec<Vec<Vec<i64>>> {
+    quantized_dct_matrices
+        .iter()
+        .zip(quantization_tables.iter())
+        .map(|(block, table)| coefficient_stats::quantized_levels(block, table))
+        .collect()
+}
+
+// Zero count, coded size and PSNR the image would get under `quantizer`
+pub fn evaluate_quantizer(
+    dct_matrices: &[Matrix],
+    quantization_tables: &[Matrix],
+    covered_original: &Matrix,
+    quantizer: Quantizer,
+) -> Result<QuantizerReport, MatrixError> {
+    let encoded = encode_and_reconstruct(dct_matrices, quantization_tables, covered_original, quantizer)?;
+    let mse = quality_metrics::mean_squared_error(covered_original, &encoded.reconstructed_image)?;
+
+    Ok(QuantizerReport {
+        quantizer,
+        zero_count: encoded.zero_count,
+        entropy_coded_size: encoded.entropy_coded_size,
+        psnr: quality_metrics::psnr_from_mse(mse),
+    })
+}
+
+pub struct EncodedImage {
+    pub zero_count: i32,
+    pub entropy_coded_size: EntropyCodedSize,
+    pub reconstructed_image: Matrix,
+}
+
+// Quantizes every block with its table and decodes it again; the output has
+// the size of `covered_original`
+pub fn encode_and_reconstruct(
+    dct_matrices: &[Matrix],
+    quantization_tables: &[Matrix],
+    covered_original: &Matrix,
+    quantizer: Quantizer,
+) -> Result<EncodedImage, MatrixError> {
+    let block_size = dct_matrices.first().ok_or(MatrixError::EmptyMatrix)?.len();
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+
+    let height = covered_original.len();
+    let width = covered_original.first().map(|row| row.len()).unwrap_or(0);
+    let mut reconstructed_image = vec![vec![0.0; width]; height];
+    let mut zero_count = 0;
+    let mut levels = Vec::with_capacity(dct_matrices.len());
+
+    let block_count = dct_matrices.len().min(quantization_tables.len());
+    let blocks = parallel::map_blocks(0..block_count, |block_index| {
+        let table = &quantization_tables[block_index];
+        let quantized_dct = quantize_dct_matrix(&dct_matrices[block_index], table, quantizer)?;
+        let reconstructed = reconstruct_image_block(
+            &quantized_dct,
+            &dct_coefficient_matrix_transposed,
+            &dct_coefficient_matrix,
+        )?;
+        Ok((quantized_dct, reconstructed))
+    });
+
+    for (block_index, block) in blocks.into_iter().enumerate() {
+        let (quantized_dct, reconstructed) = block?;
+        zero_count += count_zero_coefficients(&quantized_dct);
+        levels.push(coefficient_stats::quantized_levels(&quantized_dct, &quantization_tables[block_index]));
+        matrix_ops::merge_blocks(&mut reconstructed_image, &reconstructed, block_index, block_size)?;
+    }
+
+    Ok(EncodedImage {
+        zero_count,
+        entropy_coded_size: entropy_coding::entropy_coded_size(&levels),
+        reconstructed_image,
+    })
+}
+
+pub fn quantize_dct_matrix(
+    dct_matrix: &Matrix,
+    quantization_table: &Matrix,
+    quantizer: Quantizer,
+) -> Result<Matrix, MatrixError> {
+    let block_size = quantization_table.len();
+    if dct_matrix.len() != block_size || dct_matrix[0].len() != block_size {
         return Err(MatrixError::IncompatibleDimensions(format!(
             "Expected {}x{} matrix for quantization",
-            BLOCK_SIZE, BLOCK_SIZE
+            block_size, block_size
         )));
     }
 
-    let mut quantized = vec![vec![0.0; BLOCK_SIZE]; BLOCK_SIZE];
-    for i in 0..BLOCK_SIZE {
-        for j in 0..BLOCK_SIZE {
+    if let Quantizer::RateDistortion { lambda } = quantizer {
+        return trellis::trellis_quantize(
+            dct_matrix,
+            quantization_table,
+            lambda,
+            &HuffmanCostModel::standard(),
+        );
+    }
+
+    if let (Quantizer::Nearest, Some(coefficients), Some(table)) = (
+        quantizer,
+        simd::block_from_matrix(dct_matrix),
+        simd::block_from_matrix(quantization_table),
+    ) {
+        return Ok(simd::block_to_matrix(&simd::quantize_nearest(&coefficients, &table)));
+    }
+
+    let mut quantized = vec![vec![0.0; block_size]; block_size];
+    for i in 0..block_size {
+        for j in 0..block_size {
             quantized[i][j] =
-                (dct_matrix[i][j] / QUANTIZATION_MATRIX[i][j]).round() * QUANTIZATION_MATRIX[i][j];
+                quantizer.level(dct_matrix[i][j], quantization_table[i][j]) * quantization_table[i][j];
         }
     }
     Ok(quantized)
 }
 
-fn reconstruct_image_block(
+pub fn reconstruct_image_block(
     quantized_dct: &Matrix,
     dct_transposed: &Matrix,
     dct_matrix: &Matrix,
 ) -> Result<Matrix, MatrixError> {
-    let reconstructed = matrix_ops::multiply_chain(&[dct_transposed, quantized_dct, dct_matrix])?;
+    let reconstructed = transform_block(dct_transposed, quantized_dct, dct_matrix)?;
 
     Ok(reconstructed
         .iter()
@@ -152,17 +790,26 @@ fn reconstruct_image_block(
         .collect())
 }
 
-fn calculate_dct_coefficients(size: usize) -> Result<Matrix, MatrixError> {
+// left · block · right; 8x8 blocks go through the SIMD kernels, which give
+// the same result as the generic matrix chain
+fn transform_block(left: &Matrix, block: &Matrix, right: &Matrix) -> Result<Matrix, MatrixError> {
+    match (simd::block_from_matrix(left), simd::block_from_matrix(block), simd::block_from_matrix(right)) {
+        (Some(left), Some(block), Some(right)) => Ok(simd::block_to_matrix(&simd::transform(&left, &block, &right))),
+        _ => matrix_ops::multiply_chain(&[left, block, right]),
+    }
+}
+
+pub fn calculate_dct_coefficients(size: usize) -> Result<Matrix, MatrixError> {
     let scale_factor = f64::sqrt(2.0 / size as f64);
     let mut coefficients = vec![vec![0.0; size]; size];
 
-    for j in 0..size {
-        coefficients[0][j] = scale_factor / f64::sqrt(2.0);
+    for value in coefficients[0].iter_mut() {
+        *value = scale_factor / f64::sqrt(2.0);
     }
 
-    for i in 1..size {
-        for j in 0..size {
-            coefficients[i][j] = scale_factor
+    for (i, row) in coefficients.iter_mut().enumerate().skip(1) {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = scale_factor
                 * f64::cos((i as f64 * (2 * (j + 1) - 1) as f64 * PI) / (2 * size) as f64);
         }
     }
@@ -178,6 +825,7 @@ fn count_zero_coefficients(matrix: &Matrix) -> i32 {
         .count() as i32
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_compression_results(
     dct_matrices: &mut Vec<Matrix>,
     compressed_dct_matrices: &mut Vec<Matrix>,
@@ -224,10 +872,12 @@ fn update_compression_results(
 //     ))
 // }
 
-fn generate_mathml_documentation(
+// MathML walkthrough of one block's compression, shown next to it in the demo
+pub fn generate_mathml_documentation(
     original: &Matrix,
     normalized: &Matrix,
     dct: &Matrix,
+    quantization_table: &Matrix,
     quantized: &Matrix,
     reconstructed: &Matrix,
 ) -> Result<String, MatrixError> {
@@ -561,9 +1211,9 @@ fn generate_mathml_documentation(
         "#,
         matrix_ops::to_mathml(original)?,
         matrix_ops::to_mathml(normalized)?,
-        matrix_ops::to_mathml(&calculate_dct_coefficients(8)?)?,
+        matrix_ops::to_mathml(&calculate_dct_coefficients(quantization_table.len())?)?,
         matrix_ops::to_mathml(dct)?,
-        matrix_ops::to_mathml(&(QUANTIZATION_MATRIX.iter().map(|row| row.to_vec()).collect::<Vec<Vec<f64>>>()))?,
+        matrix_ops::to_mathml(quantization_table)?,
         matrix_ops::to_mathml(quantized)?,
         matrix_ops::to_mathml(reconstructed)?
         
@@ -573,19 +1223,198 @@ fn generate_mathml_documentation(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adaptive_quantization::ActivityMetric;
 
     #[test]
     fn test_dct_coefficients_generation() {
-        let coefficients = calculate_dct_coefficients(BLOCK_SIZE).unwrap();
-        assert_eq!(coefficients.len(), BLOCK_SIZE);
-        assert_eq!(coefficients[0].len(), BLOCK_SIZE);
+        let coefficients = calculate_dct_coefficients(8).unwrap();
+        assert_eq!(coefficients.len(), 8);
+        assert_eq!(coefficients[0].len(), 8);
+    }
+
+    #[test]
+    fn test_compress_image_with_deblocking() {
+        let image: Matrix = (0..16)
+            .map(|y| (0..16).map(|x| ((x * 13 + y * 7) % 64 + 96) as f64).collect())
+            .collect();
+        let config = CompressionConfig {
+            deblocking: Some(DeblockingFilter { strength: 1.0 }),
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image.clone(), 16, 16, &config).unwrap();
+
+        assert_eq!(result.dct_matrices.len(), 4);
+        assert!(result.latex_calculations.is_empty());
+        assert!(result.quality_metrics.psnr > 20.0);
+        let deblocking = result.deblocking.unwrap();
+        assert_eq!(deblocking.before, result.quality_metrics);
+        assert_eq!(deblocking.filtered_image.len(), 16);
+
+        let explained = CompressionConfig { explain_blocks: true, ..config };
+        assert_eq!(compress_image_dct(image, 16, 16, &explained).unwrap().latex_calculations.len(), 4);
+    }
+
+    #[test]
+    fn test_image_smaller_than_one_block() {
+        let config = CompressionConfig {
+            compare_quantizers: true,
+            pocs: Some(PocsSmoothing { iterations: 2, smoothing: 0.5 }),
+            ..CompressionConfig::default()
+        };
+        let result = compress_image_dct(vec![vec![100.0; 5]; 5], 5, 5, &config).unwrap();
+        assert!(result.dct_matrices.is_empty());
+        assert_eq!(result.coefficient_statistics.block_count, 0);
+        assert!(result.energy_compaction.blocks.is_empty());
+        assert_eq!(result.quality_metrics.ssim, 1.0);
+        assert!(result.quantizer_comparison.is_empty());
+        let pocs = result.pocs.unwrap();
+        assert_eq!(pocs.reconstructed_image, result.compressed_image);
+        assert_eq!(pocs.quality_metrics, result.quality_metrics);
+
+        let scaled = CompressionConfig {
+            quantization_table: QuantizationTableSource::AnnexKScaled { quality: 75, block_size: 8 },
+            ..CompressionConfig::default()
+        };
+        assert!(compress_image_dct(vec![vec![100.0; 5]; 5], 5, 5, &scaled).unwrap().table_comparison.is_none());
+    }
+
+    #[test]
+    fn test_dead_zone_quantizer_zeroes_more_coefficients() {
+        let image = matrix_ops::test_image(16, 16);
+        let config = CompressionConfig {
+            quantizer: Quantizer::DeadZone { width: 2.0 },
+            compare_quantizers: true,
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image.clone(), 16, 16, &config).unwrap();
+        let nearest = &result.quantizer_comparison[0];
+        let dead_zone = result.quantizer_comparison.last().unwrap();
+
+        assert_eq!(dead_zone.quantizer, config.quantizer);
+        assert_eq!(dead_zone.zero_count, result.compressed_dct_zero_count);
+        assert_eq!(dead_zone.entropy_coded_size, result.entropy_coded_size);
+        assert!(dead_zone.zero_count >= nearest.zero_count);
+        assert!(dead_zone.entropy_coded_size.total_bits <= nearest.entropy_coded_size.total_bits);
+
+        let default = compress_image_dct(image, 16, 16, &CompressionConfig::default()).unwrap();
+        assert!(default.quantizer_comparison.is_empty());
+    }
+
+    #[test]
+    fn test_trellis_quantizer_trades_bits_for_distortion() {
+        let image = matrix_ops::test_image(16, 16);
+        let config = CompressionConfig {
+            quantizer: Quantizer::RateDistortion { lambda: 100.0 },
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image, 16, 16, &config).unwrap();
+        let trellis = result.trellis.unwrap();
+
+        assert_eq!(trellis.blocks.len(), 4);
+        assert!(trellis.trellis_bits <= trellis.rounded_bits);
+        assert!(trellis.trellis_distortion >= trellis.rounded_distortion);
+    }
+
+    #[test]
+    fn test_adaptive_quantization_scales_block_tables() {
+        let mut image = vec![vec![128.0; 16]; 8];
+        for (y, row) in image.iter_mut().enumerate() {
+            for (x, value) in row.iter_mut().enumerate().skip(8) {
+                *value = if (x + y) % 2 == 0 { 30.0 } else { 230.0 };
+            }
+        }
+        let config = CompressionConfig {
+            adaptive_quantization: Some(AdaptiveQuantization {
+                metric: ActivityMetric::Variance,
+                strength: 1.0,
+            }),
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image, 16, 8, &config).unwrap();
+
+        assert_eq!(result.block_quantization_scales.len(), 2);
+        assert!(result.block_quantization_scales[0] < 1.0);
+        assert!(result.block_quantization_scales[1] > 1.0);
+        assert_eq!(result.quantization_scale_map.width, 2);
+    }
+
+    #[test]
+    fn test_region_of_interest_keeps_foreground_sharper() {
+        let image = matrix_ops::test_image(16, 8);
+        let config = CompressionConfig {
+            region_of_interest: Some(RegionOfInterest {
+                mask: vec![vec![1.0, 0.0]],
+                foreground_scale: 0.5,
+                background_scale: 2.0,
+            }),
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image, 16, 8, &config).unwrap();
+
+        assert_eq!(result.block_quantization_scales, vec![0.5, 2.0]);
+        let report = result.region_of_interest.unwrap();
+        assert_eq!((report.foreground_blocks, report.background_blocks), (1, 1));
+        assert!(report.foreground_psnr.unwrap() > report.background_psnr.unwrap());
+    }
+
+    #[test]
+    fn test_perceptual_table_sets_block_size() {
+        let image = matrix_ops::test_image(32, 32);
+        let config = CompressionConfig {
+            quantization_table: QuantizationTableSource::Perceptual(ViewingConditions {
+                viewing_distance: 24.0,
+                display_dpi: 96.0,
+                block_size: 16,
+                peak_step: 10.0,
+            }),
+            ..CompressionConfig::default()
+        };
+
+        let result = compress_image_dct(image, 32, 32, &config).unwrap();
+
+        assert_eq!(result.quantization_table.len(), 16);
+        assert_eq!(result.dct_matrices.len(), 4);
+        let comparison = result.table_comparison.unwrap();
+        assert_eq!(comparison.annex_k.quantizer, Quantizer::Nearest);
+        assert!(comparison.selected.psnr.is_finite() && comparison.annex_k.psnr.is_finite());
+    }
+
+    #[test]
+    fn test_compression_job_steps_match_single_call() {
+        let image = matrix_ops::test_image(16, 24);
+        let config = CompressionConfig::default();
+        let expected = compress_image_dct(image.clone(), 16, 24, &config).unwrap();
+
+        let mut job = CompressionJob::new(image.clone(), 16, 24, config.clone()).unwrap();
+        let progress = job.step(4).unwrap();
+        assert_eq!((progress.processed_blocks, progress.total_blocks, progress.done), (4, 6, false));
+        let progress = job.step(4).unwrap();
+        assert_eq!((progress.processed_blocks, progress.completed_stages, progress.total_stages), (6, 0, 2));
+        assert_eq!(progress.next_stage, Some(AnalysisStage::Quality));
+        let progress = job.step(4).unwrap();
+        assert_eq!(progress.next_stage, Some(AnalysisStage::Statistics));
+        assert!(!progress.done);
+        assert!(job.step(4).unwrap().done);
+        let result = job.finish().unwrap();
+        assert_eq!(result.compressed_image, expected.compressed_image);
+        assert_eq!(result.compressed_dct_matrices, expected.compressed_dct_matrices);
+        assert_eq!(result.entropy_coded_size, expected.entropy_coded_size);
+
+        let mut unfinished = CompressionJob::new(image, 16, 24, config).unwrap();
+        unfinished.step(1).unwrap();
+        assert!(unfinished.finish().is_err());
     }
 
     #[test]
     fn test_quantization() {
-        let input = vec![vec![1.0; BLOCK_SIZE]; BLOCK_SIZE];
-        let quantized = quantize_dct_matrix(&input).unwrap();
-        assert_eq!(quantized.len(), BLOCK_SIZE);
-        assert_eq!(quantized[0].len(), BLOCK_SIZE);
+        let input = vec![vec![1.0; 8]; 8];
+        let quantized = quantize_dct_matrix(&input, &quantization_table(), Quantizer::Nearest).unwrap();
+        assert_eq!(quantized.len(), 8);
+        assert_eq!(quantized[0].len(), 8);
     }
 }
\ No newline at end of file
@@ -0,0 +1,439 @@
+// `.dctz`: a compact container for the quantized coefficients of a compressed
+// image, so a compressed state can be saved and reconstructed later. Unlike
+// JFIF it keeps any block size, per-block quantization scales (adaptive
+// quantization, regions of interest) and non-integer table steps.
+//
+// Layout, integers big-endian:
+//   magic "DCTZ", version u8 (1)
+//   width u32, height u32      size of the reconstructed image
+//   block_size u16
+//   channel_layout u8          0 = grayscale (luma)
+//   edge_mode u8               0 = crop, 1 = replicate, 2 = mirror, 3 = zero
+//   flags u8                   bit 0: per-block scales follow the table,
+//                              bit 1: table steps are f64 instead of u16
+//   quantization table         block_size² steps, row-major
+//   block scales               one f64 per block, row-major (flag bit 0 only)
+//   DC table, AC table         JPEG DHT style: 16 code counts, then the symbols
+//   data_length u32, data      JPEG-style Huffman coded levels of every block,
+//                              row-major, DC coded as the difference to the
+//                              previous block, no byte stuffing
+//
+// Blocks cover the image rounded up to whole blocks; the reconstruction is
+// cropped back to width x height.
+use crate::adaptive_quantization::scale_quantization_table;
+use crate::dct_compression::{self, calculate_dct_coefficients, reconstruct_image_block};
+use crate::encoder::Compressed;
+use crate::entropy_coding::{
+    self, magnitude_category, HuffmanTable, SymbolClass, END_OF_BLOCK, MAX_CATEGORY, ZERO_RUN_LENGTH,
+};
+use crate::error::{Error, Result};
+use crate::matrix_ops::{self, EdgeMode, Matrix};
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8] = b"DCTZ";
+const VERSION: u8 = 1;
+const FLAG_BLOCK_SCALES: u8 = 1;
+const FLAG_FLOAT_STEPS: u8 = 2;
+
+// Only luma is compressed today; the header byte leaves room for color layouts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelLayout {
+    Gray,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DctzImage {
+    pub width: usize,
+    pub height: usize,
+    pub block_size: usize,
+    pub channel_layout: ChannelLayout,
+    pub edge_mode: EdgeMode,
+    pub quantization_table: Matrix,
+    // Row-major, one per block; the block's steps are the table times its scale
+    pub block_quantization_scales: Vec<f64>,
+    // Integer levels of every block, row-major
+    pub levels: Vec<Vec<Vec<i64>>>,
+}
+
+impl DctzImage {
+    pub fn from_compressed(compressed: &Compressed) -> Self {
+        let details = &compressed.details;
+        let tables: Vec<Matrix> = details
+            .block_quantization_scales
+            .iter()
+            .map(|&scale| scale_quantization_table(&details.quantization_table, scale))
+            .collect();
+        Self {
+            width: compressed.width,
+            height: compressed.height,
+            block_size: compressed.block_size,
+            channel_layout: ChannelLayout::Gray,
+            edge_mode: compressed.edge_mode,
+            quantization_table: details.quantization_table.clone(),
+            block_quantization_scales: details.block_quantization_scales.clone(),
+            levels: dct_compression::block_levels(&details.compressed_dct_matrices, &tables),
+        }
+    }
+
+    pub fn blocks_x(&self) -> usize {
+        self.width.div_ceil(self.block_size)
+    }
+
+    pub fn blocks_y(&self) -> usize {
+        self.height.div_ceil(self.block_size)
+    }
+
+    // Dequantizes and inverse-transforms every block; equal to the encoder's
+    // `reconstructed` (cropped, clamped to 0–255)
+    pub fn reconstruct(&self) -> Result<Matrix> {
+        self.validate()?;
+        let block_size = self.block_size;
+        let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+        let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+
+        let mut image = vec![vec![0.0; self.blocks_x() * block_size]; self.blocks_y() * block_size];
+        for (block_index, (levels, &scale)) in self.levels.iter().zip(&self.block_quantization_scales).enumerate() {
+            let table = scale_quantization_table(&self.quantization_table, scale);
+            let quantized_dct: Matrix = levels
+                .iter()
+                .zip(&table)
+                .map(|(level_row, step_row)| {
+                    level_row.iter().zip(step_row).map(|(&level, &step)| level as f64 * step).collect()
+                })
+                .collect();
+            let block = reconstruct_image_block(
+                &quantized_dct,
+                &dct_coefficient_matrix_transposed,
+                &dct_coefficient_matrix,
+            )?;
+            matrix_ops::merge_blocks(&mut image, &block, block_index, block_size)?;
+        }
+
+        Ok(matrix_ops::crop(&image, self.height, self.width)
+            .iter()
+            .map(|row| row.iter().map(|value| value.clamp(0.0, 255.0)).collect())
+            .collect())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let mut previous_dc = 0;
+        for block in &self.levels {
+            let dc_difference = block[0][0] - previous_dc;
+            previous_dc = block[0][0];
+            if block.iter().flatten().chain([&dc_difference]).any(|&level| magnitude_category(level) > MAX_CATEGORY) {
+                return Err(Error::InvalidImage(
+                    "Quantized levels need more than 15 bits; use larger quantization steps".to_string(),
+                ));
+            }
+        }
+
+        let steps: Vec<f64> = self.quantization_table.iter().flatten().copied().collect();
+        let integer_steps = steps.iter().all(|&step| step.fract() == 0.0 && (1.0..=u16::MAX as f64).contains(&step));
+        let uniform = self.block_quantization_scales.iter().all(|&scale| scale == 1.0);
+        let mut flags = 0;
+        if !uniform {
+            flags |= FLAG_BLOCK_SCALES;
+        }
+        if !integer_steps {
+            flags |= FLAG_FLOAT_STEPS;
+        }
+
+        let mut output = MAGIC.to_vec();
+        output.push(VERSION);
+        output.extend_from_slice(&(self.width as u32).to_be_bytes());
+        output.extend_from_slice(&(self.height as u32).to_be_bytes());
+        output.extend_from_slice(&(self.block_size as u16).to_be_bytes());
+        output.push(match self.channel_layout {
+            ChannelLayout::Gray => 0,
+        });
+        output.push(match self.edge_mode {
+            EdgeMode::Crop => 0,
+            EdgeMode::Replicate => 1,
+            EdgeMode::Mirror => 2,
+            EdgeMode::Zero => 3,
+        });
+        output.push(flags);
+        for step in steps {
+            if integer_steps {
+                output.extend_from_slice(&(step as u16).to_be_bytes());
+            } else {
+                output.extend_from_slice(&step.to_be_bytes());
+            }
+        }
+        if !uniform {
+            for scale in &self.block_quantization_scales {
+                output.extend_from_slice(&scale.to_be_bytes());
+            }
+        }
+
+        let symbols = entropy_coding::image_symbols(&self.levels);
+        let (dc_table, ac_table) = entropy_coding::optimized_tables(&symbols);
+        for table in [&dc_table, &ac_table] {
+            output.extend_from_slice(&table.bits);
+            output.extend_from_slice(&table.values);
+        }
+        let data = entropy_coding::encode_symbols(&symbols, &dc_table, &ac_table, false);
+        output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        output.extend(data);
+        Ok(output)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 || self.width > u32::MAX as usize || self.height > u32::MAX as usize {
+            return Err(Error::InvalidImage(format!("Invalid image size {}x{}", self.width, self.height)));
+        }
+        if !(1..=u16::MAX as usize).contains(&self.block_size) {
+            return Err(Error::InvalidParameter(format!("Invalid block size {}", self.block_size)));
+        }
+        let block_count = self.blocks_x() * self.blocks_y();
+        if !is_square(&self.quantization_table, self.block_size)
+            || self.levels.len() != block_count
+            || self.block_quantization_scales.len() != block_count
+            || !self.levels.iter().all(|block| is_square(block, self.block_size))
+        {
+            return Err(Error::InvalidImage(format!(
+                "Expected {} blocks of {}x{} levels and a matching table",
+                block_count, self.block_size, self.block_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn is_square<T>(matrix: &[Vec<T>], size: usize) -> bool {
+    matrix.len() == size && matrix.iter().all(|row| row.len() == size)
+}
+
+// Container bytes of an `Encoder` result
+pub fn encode(compressed: &Compressed) -> Result<Vec<u8>> {
+    DctzImage::from_compressed(compressed).to_bytes()
+}
+
+pub fn decode(bytes: &[u8]) -> Result<DctzImage> {
+    let mut reader = ByteReader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(Error::Decode("Missing DCTZ signature".to_string()));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(Error::Decode(format!("Unsupported DCTZ version {}", version)));
+    }
+    let width = reader.u32()? as usize;
+    let height = reader.u32()? as usize;
+    let block_size = reader.u16()? as usize;
+    let channel_layout = match reader.u8()? {
+        0 => ChannelLayout::Gray,
+        other => return Err(Error::Decode(format!("Unknown DCTZ channel layout {}", other))),
+    };
+    let edge_mode = match reader.u8()? {
+        0 => EdgeMode::Crop,
+        1 => EdgeMode::Replicate,
+        2 => EdgeMode::Mirror,
+        3 => EdgeMode::Zero,
+        other => return Err(Error::Decode(format!("Unknown DCTZ edge mode {}", other))),
+    };
+    let flags = reader.u8()?;
+    if width == 0 || height == 0 || block_size == 0 {
+        return Err(Error::Decode("Invalid DCTZ header".to_string()));
+    }
+    let block_count = width
+        .div_ceil(block_size)
+        .checked_mul(height.div_ceil(block_size))
+        .ok_or_else(|| Error::Decode(format!("DCTZ image {}x{} is too large", width, height)))?;
+    // Every block takes at least one bit of coefficient data (and 8 bytes of
+    // scale when there are scales), so a header whose size the rest of the file
+    // cannot back is rejected before anything is allocated for it
+    let scale_bytes = if flags & FLAG_BLOCK_SCALES != 0 { 8 } else { 0 };
+    let remaining = reader.remaining();
+    if block_count > remaining.saturating_mul(8) || block_count.saturating_mul(scale_bytes) > remaining {
+        return Err(Error::Decode(format!(
+            "DCTZ data is too short for {} blocks of a {}x{} image",
+            block_count, width, height
+        )));
+    }
+
+    let quantization_table = (0..block_size)
+        .map(|_| {
+            (0..block_size)
+                .map(|_| if flags & FLAG_FLOAT_STEPS != 0 { reader.f64() } else { reader.u16().map(f64::from) })
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Matrix>>()?;
+    let block_quantization_scales = if flags & FLAG_BLOCK_SCALES != 0 {
+        (0..block_count).map(|_| reader.f64()).collect::<Result<Vec<_>>>()?
+    } else {
+        vec![1.0; block_count]
+    };
+
+    let dc_table = reader.huffman_table(SymbolClass::Dc)?;
+    let ac_table = reader.huffman_table(SymbolClass::Ac)?;
+    let data_length = reader.u32()? as usize;
+    let data = reader.take(data_length)?;
+    if block_count > data.len().saturating_mul(8) {
+        return Err(Error::Decode("DCTZ coefficient data is truncated".to_string()));
+    }
+    let levels = entropy_coding::decode_blocks(data, &dc_table, &ac_table, block_size, block_count)
+        .ok_or_else(|| Error::Decode("DCTZ coefficient data is corrupt".to_string()))?;
+
+    Ok(DctzImage {
+        width,
+        height,
+        block_size,
+        channel_layout,
+        edge_mode,
+        quantization_table,
+        block_quantization_scales,
+        levels,
+    })
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| Error::Decode("DCTZ data is truncated".to_string()))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    // A DHT-style table, rejected unless its codes fit their lengths and every
+    // symbol is a distinct one the coefficient data can use
+    fn huffman_table(&mut self, class: SymbolClass) -> Result<HuffmanTable> {
+        let malformed = || Error::Decode(format!("DCTZ {:?} Huffman table is malformed", class));
+        let bits: [u8; 16] = self.array()?;
+        // Codes still free at each length, starting from the single empty prefix
+        let mut free_codes = 1usize;
+        for &count in &bits {
+            free_codes = (2 * free_codes).checked_sub(count as usize).ok_or_else(malformed)?;
+        }
+        let count = bits.iter().map(|&count| count as usize).sum();
+        let values = self.take(count)?.to_vec();
+
+        let mut seen = [false; 256];
+        for &symbol in &values {
+            let valid = match class {
+                SymbolClass::Dc => symbol <= MAX_CATEGORY,
+                SymbolClass::Ac => symbol & 0x0F != 0 || symbol == END_OF_BLOCK || symbol == ZERO_RUN_LENGTH,
+            };
+            if !valid || std::mem::replace(&mut seen[symbol as usize], true) {
+                return Err(malformed());
+            }
+        }
+        Ok(HuffmanTable { bits, values })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive_quantization::{ActivityMetric, AdaptiveQuantization};
+    use crate::encoder::Encoder;
+    use crate::perceptual_table::ViewingConditions;
+    use crate::quantizer::Quantizer;
+    use crate::QuantizationTableSource;
+
+    #[test]
+    fn test_round_trip_reconstructs_encoder_output() {
+        let image = matrix_ops::test_image(27, 19);
+        let encoders = [
+            Encoder::new().quality(75),
+            Encoder::new().block_size(4).quantizer(Quantizer::DeadZone { width: 1.5 }).edge_mode(EdgeMode::Mirror),
+            Encoder::new().block_size(16).edge_mode(EdgeMode::Crop),
+            Encoder::new().adaptive_quantization(AdaptiveQuantization {
+                metric: ActivityMetric::Variance,
+                strength: 1.0,
+            }),
+            Encoder::new().quantization_table(QuantizationTableSource::Perceptual(ViewingConditions {
+                viewing_distance: 20.0,
+                display_dpi: 96.0,
+                block_size: 8,
+                peak_step: 10.0,
+            })),
+        ];
+
+        for encoder in encoders {
+            let compressed = encoder.encode(&image).unwrap();
+            let bytes = encode(&compressed).unwrap();
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded, DctzImage::from_compressed(&compressed));
+            assert_eq!(decoded.reconstruct().unwrap(), compressed.reconstructed);
+        }
+
+        let bytes = encode(&Encoder::new().encode(&image).unwrap()).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(b"DCTZ\x02").is_err());
+    }
+
+    #[test]
+    fn test_rejects_sizes_the_data_cannot_hold() {
+        // 65536x65536 at block size 1 is 2^32 blocks, in a 50-byte file
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&[0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 1, FLAG_BLOCK_SCALES]);
+        bytes.resize(50, 0);
+        match decode(&bytes) {
+            Err(Error::Decode(message)) => assert!(message.contains("too short for 4294967296 blocks"), "{}", message),
+            other => panic!("Expected a decode error, got {:?}", other.map(|image| image.width)),
+        }
+    }
+
+    #[test]
+    fn test_rejects_corrupt_huffman_tables() {
+        let image: Matrix = (0..16).map(|y| (0..16).map(|x| ((x * 31 + y * 7) % 200) as f64).collect()).collect();
+        let bytes = encode(&Encoder::new().encode(&image).unwrap()).unwrap();
+        // The DC table starts after the header and the 64 u16 table steps
+        let dc_table = MAGIC.len() + 1 + 4 + 4 + 2 + 3 + 2 * 64;
+        let dc_count: usize = bytes[dc_table..dc_table + 16].iter().map(|&count| count as usize).sum();
+
+        let mut out_of_range = bytes.clone();
+        out_of_range[dc_table + 16] = 40;
+        let mut duplicate = bytes.clone();
+        duplicate[dc_table + 17] = duplicate[dc_table + 16];
+        let mut oversubscribed = bytes.clone();
+        oversubscribed[dc_table] = 3;
+        let mut bad_ac_symbol = bytes.clone();
+        bad_ac_symbol[dc_table + 16 + dc_count + 16] = 0x50;
+
+        assert!(dc_count >= 2);
+        for corrupt in [out_of_range, duplicate, oversubscribed, bad_ac_symbol] {
+            match decode(&corrupt) {
+                Err(Error::Decode(message)) => assert!(message.contains("Huffman table is malformed"), "{}", message),
+                other => panic!("Expected a decode error, got {:?}", other.map(|image| image.width)),
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use crate::quality_metrics::QualityMetrics;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DeblockingFilter {
+    // Multiplier on the quantization step that decides which edges count as artifacts
+    pub strength: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeblockingResult {
+    pub strength: f64,
+    pub filtered_image: Matrix,
+    pub before: QualityMetrics,
+    pub after: QualityMetrics,
+}
+
+// Simplified H.264-style edge filter. A step across a block boundary is only
+// smoothed when it is small compared to the quantization step (`alpha`) and
+// both sides are locally flat (`beta`), so real image edges are left alone.
+// The correction of p0/q0 is clipped to `tc`. Each block's step is
+// `quantization_step` times its entry in `block_scales` (row-major over the
+// whole blocks), and an edge uses the mean of the steps on its two sides.
+pub fn deblock(
+    image: &Matrix,
+    block_size: usize,
+    quantization_step: f64,
+    block_scales: &[f64],
+    filter: DeblockingFilter,
+) -> Result<Matrix, MatrixError> {
+    if block_size < 2 {
+        return Err(MatrixError::InvalidParameter(
+            "Deblocking needs blocks of at least 2x2 pixels".to_string(),
+        ));
+    }
+    if filter.strength.is_nan() || filter.strength < 0.0 {
+        return Err(MatrixError::InvalidParameter(
+            "Deblocking strength must be a non-negative number".to_string(),
+        ));
+    }
+
+    let height = image.len();
+    let width = image.first().map(|row| row.len()).unwrap_or(0);
+    if width == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+
+    let (blocks_x, blocks_y) = (width / block_size, height / block_size);
+    if block_scales.len() != blocks_x * blocks_y {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "{} block scales for a {}x{} grid of blocks",
+            block_scales.len(),
+            blocks_x,
+            blocks_y
+        )));
+    }
+    // Alpha of every block; beta and tc follow from it
+    let block_alphas: Matrix = if blocks_x == 0 {
+        Vec::new()
+    } else {
+        block_scales
+            .chunks(blocks_x)
+            .map(|row| row.iter().map(|&scale| filter.strength * quantization_step * scale).collect())
+            .collect()
+    };
+
+    // Vertical block edges first, then horizontal ones (as vertical edges of
+    // the transposed image), in the same order as H.264
+    let mut filtered = image.clone();
+    filter_vertical_edges(&mut filtered, block_size, &block_alphas);
+    if block_alphas.is_empty() {
+        return Ok(filtered);
+    }
+    let mut transposed = matrix_ops::transpose(&filtered)?;
+    let transposed_alphas = matrix_ops::transpose(&block_alphas)?;
+    filter_vertical_edges(&mut transposed, block_size, &transposed_alphas);
+    matrix_ops::transpose(&transposed)
+}
+
+// Edges inside the area covered by `block_alphas`, one alpha per block
+fn filter_vertical_edges(image: &mut Matrix, block_size: usize, block_alphas: &Matrix) {
+    for (row_index, row) in image.iter_mut().take(block_alphas.len() * block_size).enumerate() {
+        let alphas = &block_alphas[row_index / block_size];
+        for edge in (block_size..alphas.len() * block_size).step_by(block_size) {
+            let alpha = (alphas[edge / block_size - 1] + alphas[edge / block_size]) / 2.0;
+            let (beta, tc) = (alpha / 2.0, alpha / 4.0);
+            let [p1, p0, q0, q1] = [row[edge - 2], row[edge - 1], row[edge], row[edge + 1]];
+            if let Some(delta) = edge_correction(p1, p0, q0, q1, alpha, beta, tc) {
+                row[edge - 1] = p0 + delta;
+                row[edge] = q0 - delta;
+            }
+        }
+    }
+}
+
+fn edge_correction(p1: f64, p0: f64, q0: f64, q1: f64, alpha: f64, beta: f64, tc: f64) -> Option<f64> {
+    let is_artifact = (p0 - q0).abs() < alpha && (p1 - p0).abs() < beta && (q1 - q0).abs() < beta;
+    if !is_artifact {
+        return None;
+    }
+
+    let delta = ((q0 - p0) * 4.0 + (p1 - q1)) / 8.0;
+    Some(delta.clamp(-tc, tc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_block_step_is_smoothed() {
+        let image: Matrix = (0..8)
+            .map(|_| (0..16).map(|x| if x < 8 { 100.0 } else { 106.0 }).collect())
+            .collect();
+
+        let filtered = deblock(&image, 8, 16.0, &[1.0, 1.0], DeblockingFilter { strength: 1.0 }).unwrap();
+
+        assert_eq!(filtered[0][7], 102.25);
+        assert_eq!(filtered[0][8], 103.75);
+        assert_eq!(filtered[0][6], 100.0);
+    }
+
+    #[test]
+    fn test_strong_edge_is_preserved() {
+        let image: Matrix = (0..8)
+            .map(|_| (0..16).map(|x| if x < 8 { 20.0 } else { 200.0 }).collect())
+            .collect();
+
+        let filtered = deblock(&image, 8, 16.0, &[1.0, 1.0], DeblockingFilter { strength: 1.0 }).unwrap();
+
+        assert_eq!(filtered, image);
+    }
+
+    #[test]
+    fn test_threshold_follows_block_scales() {
+        // A 12-level step is an artifact between blocks quantized with step
+        // 16, but not next to a finely quantized block
+        let image: Matrix = (0..16)
+            .map(|_| (0..16).map(|x| if x < 8 { 100.0 } else { 112.0 }).collect())
+            .collect();
+
+        let filtered = deblock(&image, 8, 16.0, &[1.0, 1.0, 0.25, 0.25], DeblockingFilter { strength: 1.0 }).unwrap();
+
+        assert_ne!(filtered[3][7], image[3][7]);
+        assert_eq!(filtered[12][7], image[12][7]);
+        assert!(deblock(&image, 8, 16.0, &[1.0; 2], DeblockingFilter { strength: 1.0 }).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::adaptive_quantization::AdaptiveQuantization;
+use crate::dct_compression::{self, CompressionConfig, CompressionResult, QuantizationTableSource};
+use crate::deblocking::DeblockingFilter;
+use crate::error::{Error, Result};
+use crate::error_maps::Colormap;
+use crate::matrix_ops::{self, EdgeMode, Matrix};
+use crate::pocs::PocsSmoothing;
+use crate::quality_metrics::{self, QualityMetrics};
+use crate::quality_scaling::DEFAULT_QUALITY;
+use crate::quantizer::Quantizer;
+use crate::region_of_interest::RegionOfInterest;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BLOCK_SIZE: usize = 8;
+
+// Builder for the compression pipeline, e.g.
+// `Encoder::new().quality(75).quantizer(Quantizer::Truncate).encode(&image)`
+#[derive(Clone, Debug)]
+pub struct Encoder {
+    config: CompressionConfig,
+    quality: u32,
+    block_size: usize,
+    // Set by `quantization_table`, replaces quality/block size
+    table: Option<QuantizationTableSource>,
+    edge_mode: EdgeMode,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Compressed {
+    // Size of `reconstructed`: the input size, or the whole-block area with `EdgeMode::Crop`
+    pub width: usize,
+    pub height: usize,
+    pub block_size: usize,
+    pub edge_mode: EdgeMode,
+    // Clamped to 0–255
+    pub reconstructed: Matrix,
+    // Of `reconstructed` against the matching part of the input
+    pub quality_metrics: QualityMetrics,
+    // Full pipeline output for the padded (or cropped) image
+    pub details: CompressionResult,
+}
+
+impl Compressed {
+    pub fn bits_per_pixel(&self) -> f64 {
+        self.details.entropy_coded_size.total_bits as f64 / (self.width * self.height) as f64
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {
+            config: CompressionConfig::default(),
+            quality: DEFAULT_QUALITY,
+            block_size: DEFAULT_BLOCK_SIZE,
+            table: None,
+            edge_mode: EdgeMode::default(),
+        }
+    }
+
+    // Starts from an existing configuration; its table is kept as is
+    pub fn from_config(config: CompressionConfig) -> Self {
+        Self {
+            table: Some(config.quantization_table.clone()),
+            config,
+            ..Self::new()
+        }
+    }
+
+    // libjpeg-style quality (1–100) applied to the Annex K table
+    pub fn quality(mut self, quality: u32) -> Self {
+        self.quality = quality;
+        self.table = None;
+        self
+    }
+
+    // Annex K resampled to this block size
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self.table = None;
+        self
+    }
+
+    // Any table source; the block size follows the table
+    pub fn quantization_table(mut self, source: QuantizationTableSource) -> Self {
+        self.table = Some(source);
+        self
+    }
+
+    pub fn quantizer(mut self, quantizer: Quantizer) -> Self {
+        self.config.quantizer = quantizer;
+        self
+    }
+
+    pub fn edge_mode(mut self, edge_mode: EdgeMode) -> Self {
+        self.edge_mode = edge_mode;
+        self
+    }
+
+    pub fn colormap(mut self, colormap: Colormap) -> Self {
+        self.config.colormap = colormap;
+        self
+    }
+
+    pub fn compaction_coefficients(mut self, k: usize) -> Self {
+        self.config.compaction_coefficients = k;
+        self
+    }
+
+    // Fills `quantizer_comparison` in the details
+    pub fn compare_quantizers(mut self, compare: bool) -> Self {
+        self.config.compare_quantizers = compare;
+        self
+    }
+
+    // Fills `latex_calculations` in the details
+    pub fn explain_blocks(mut self, explain: bool) -> Self {
+        self.config.explain_blocks = explain;
+        self
+    }
+
+    pub fn deblocking(mut self, filter: DeblockingFilter) -> Self {
+        self.config.deblocking = Some(filter);
+        self
+    }
+
+    pub fn pocs(mut self, params: PocsSmoothing) -> Self {
+        self.config.pocs = Some(params);
+        self
+    }
+
+    pub fn adaptive_quantization(mut self, params: AdaptiveQuantization) -> Self {
+        self.config.adaptive_quantization = Some(params);
+        self
+    }
+
+    // A per-pixel mask must match the input size; it is padded like the image
+    pub fn region_of_interest(mut self, roi: RegionOfInterest) -> Self {
+        self.config.region_of_interest = Some(roi);
+        self
+    }
+
+    pub fn table_source(&self) -> QuantizationTableSource {
+        match &self.table {
+            Some(source) => source.clone(),
+            None if self.quality == DEFAULT_QUALITY && self.block_size == DEFAULT_BLOCK_SIZE => {
+                QuantizationTableSource::AnnexK
+            }
+            None => QuantizationTableSource::AnnexKScaled {
+                quality: self.quality,
+                block_size: self.block_size,
+            },
+        }
+    }
+
+    pub fn encode(&self, image: &Matrix) -> Result<Compressed> {
+        let (input_height, input_width) = validate_image(image)?;
+        let mut config = self.config.clone();
+        config.quantization_table = self.table_source();
+        let block_size = config.quantization_table.table()?.len();
+
+        let fitted = matrix_ops::fit_to_blocks(image, block_size, self.edge_mode)?;
+        let (height, width) = match self.edge_mode {
+            EdgeMode::Crop => (fitted.len(), fitted.first().map_or(0, |row| row.len())),
+            _ => (input_height, input_width),
+        };
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidImage(format!(
+                "{}x{} image is smaller than one {}x{} block",
+                input_width, input_height, block_size, block_size
+            )));
+        }
+
+        if let Some(roi) = config.region_of_interest.as_mut() {
+            if roi.mask.len() == input_height && roi.mask.iter().all(|row| row.len() == input_width) {
+                roi.mask = matrix_ops::fit_to_blocks(&roi.mask, block_size, self.edge_mode)?;
+            }
+        }
+
+        let fitted_width = fitted[0].len();
+        let fitted_height = fitted.len();
+        let details = dct_compression::compress_image_dct(fitted, fitted_width, fitted_height, &config)?;
+
+        let reconstructed: Matrix = matrix_ops::crop(&details.compressed_image, height, width)
+            .iter()
+            .map(|row| row.iter().map(|value| value.clamp(0.0, 255.0)).collect())
+            .collect();
+        let quality_metrics = quality_metrics::measure_quality(&matrix_ops::crop(image, height, width), &reconstructed)?;
+
+        Ok(Compressed {
+            width,
+            height,
+            block_size,
+            edge_mode: self.edge_mode,
+            reconstructed,
+            quality_metrics,
+            details,
+        })
+    }
+}
+
+// Returns (height, width) of a non-empty rectangular image with finite pixels
+fn validate_image(image: &Matrix) -> Result<(usize, usize)> {
+    let width = image.first().map_or(0, |row| row.len());
+    if width == 0 {
+        return Err(Error::InvalidImage("Image is empty".to_string()));
+    }
+    if image.iter().any(|row| row.len() != width) {
+        return Err(Error::InvalidImage("Image rows must all have the same length".to_string()));
+    }
+    if image.iter().flatten().any(|value| !value.is_finite()) {
+        return Err(Error::InvalidImage("Pixel values must be finite".to_string()));
+    }
+    Ok((image.len(), width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_pads_and_crops_back() {
+        let image = matrix_ops::test_image(21, 13);
+
+        let compressed = Encoder::new().quality(90).encode(&image).unwrap();
+        assert_eq!((compressed.width, compressed.height), (21, 13));
+        assert_eq!(compressed.details.original_image.len(), 16);
+        assert!(compressed.quality_metrics.psnr > 30.0);
+
+        let cropped = Encoder::new().edge_mode(EdgeMode::Crop).encode(&image).unwrap();
+        assert_eq!((cropped.width, cropped.height), (16, 8));
+
+        assert!(matches!(Encoder::new().quality(0).encode(&image), Err(Error::InvalidParameter(_))));
+        assert!(matches!(
+            Encoder::new().edge_mode(EdgeMode::Crop).encode(&vec![vec![0.0; 4]; 4]).map(|_| ()),
+            Err(Error::InvalidImage(_))
+        ));
+    }
+}
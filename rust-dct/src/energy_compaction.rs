@@ -0,0 +1,117 @@
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_COMPACTION_COEFFICIENTS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockEnergyCompaction {
+    pub total_energy: f64,
+    pub dc_fraction: f64,
+    pub first_k_fraction: f64,
+    // cumulative_fraction[n] is the share of energy in the first n + 1 zigzag coefficients
+    pub cumulative_fraction: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnergyCompaction {
+    pub coefficients_considered: usize,
+    pub blocks: Vec<BlockEnergyCompaction>,
+    pub average: BlockEnergyCompaction,
+}
+
+pub fn block_energy_compaction(dct_matrix: &Matrix, k: usize) -> BlockEnergyCompaction {
+    let energies: Vec<f64> = matrix_ops::zigzag_indices(dct_matrix.len())
+        .into_iter()
+        .map(|(row, col)| dct_matrix[row][col] * dct_matrix[row][col])
+        .collect();
+    let total_energy: f64 = energies.iter().sum();
+
+    // A perfectly flat block has nothing to compact: treat it as fully captured
+    let mut running = 0.0;
+    let cumulative_fraction: Vec<f64> = energies
+        .iter()
+        .map(|&energy| {
+            running += energy;
+            if total_energy > 0.0 { running / total_energy } else { 1.0 }
+        })
+        .collect();
+
+    BlockEnergyCompaction {
+        total_energy,
+        dc_fraction: cumulative_fraction.first().copied().unwrap_or(1.0),
+        first_k_fraction: fraction_at(&cumulative_fraction, k),
+        cumulative_fraction,
+    }
+}
+
+fn fraction_at(cumulative_fraction: &[f64], k: usize) -> f64 {
+    match k {
+        0 => 0.0,
+        _ => cumulative_fraction
+            .get(k - 1)
+            .or(cumulative_fraction.last())
+            .copied()
+            .unwrap_or(1.0),
+    }
+}
+
+pub fn compute_energy_compaction(dct_matrices: &[Matrix], k: usize) -> Result<EnergyCompaction, MatrixError> {
+    // An image smaller than one block averages like a flat block
+    let Some(first_block) = dct_matrices.first() else {
+        return Ok(EnergyCompaction {
+            coefficients_considered: k,
+            blocks: Vec::new(),
+            average: block_energy_compaction(&Vec::new(), k),
+        });
+    };
+    let coefficient_count = first_block.len() * first_block.len();
+    let blocks: Vec<BlockEnergyCompaction> = dct_matrices
+        .iter()
+        .map(|dct_matrix| block_energy_compaction(dct_matrix, k))
+        .collect();
+
+    let block_count = blocks.len() as f64;
+    let mut cumulative_fraction = vec![0.0; coefficient_count];
+    for block in &blocks {
+        for (average, &fraction) in cumulative_fraction.iter_mut().zip(block.cumulative_fraction.iter()) {
+            *average += fraction / block_count;
+        }
+    }
+
+    let average = BlockEnergyCompaction {
+        total_energy: blocks.iter().map(|block| block.total_energy).sum::<f64>() / block_count,
+        dc_fraction: blocks.iter().map(|block| block.dc_fraction).sum::<f64>() / block_count,
+        first_k_fraction: blocks.iter().map(|block| block.first_k_fraction).sum::<f64>() / block_count,
+        cumulative_fraction,
+    };
+
+    Ok(EnergyCompaction {
+        coefficients_considered: k,
+        blocks,
+        average,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_energy_fractions() {
+        let mut dct_block = vec![vec![0.0; 8]; 8];
+        dct_block[0][0] = 3.0;
+        dct_block[1][0] = 1.0;
+        dct_block[7][7] = 2.0;
+
+        let compaction = block_energy_compaction(&dct_block, 3);
+
+        assert_eq!(compaction.total_energy, 14.0);
+        assert_eq!(compaction.dc_fraction, 9.0 / 14.0);
+        assert_eq!(compaction.first_k_fraction, 10.0 / 14.0);
+        assert_eq!(compaction.cumulative_fraction[63], 1.0);
+
+        let empty = compute_energy_compaction(&[], 3).unwrap();
+        assert!(empty.blocks.is_empty());
+        assert_eq!((empty.average.total_energy, empty.average.dc_fraction), (0.0, 1.0));
+    }
+}
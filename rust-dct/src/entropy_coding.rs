@@ -0,0 +1,571 @@
+use crate::matrix_ops;
+use serde::{Deserialize, Serialize};
+
+// JPEG-style symbols: a DC symbol is the magnitude category of the DPCM
+// difference, an AC symbol packs (zero run << 4) | category. Both are followed
+// by `category` raw amplitude bits.
+pub const END_OF_BLOCK: u8 = 0x00;
+pub const ZERO_RUN_LENGTH: u8 = 0xF0;
+const MAX_RUN: usize = 15;
+const MAX_CODE_LENGTH: usize = 16;
+// Amplitudes carry at most 15 bits in the JPEG symbol scheme
+pub const MAX_CATEGORY: u8 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolClass {
+    Dc,
+    Ac,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub class: SymbolClass,
+    pub code: u8,
+    pub amplitude: i64,
+}
+
+impl Symbol {
+    pub fn amplitude_bits(&self) -> usize {
+        (self.code & 0x0F) as usize
+    }
+}
+
+// Number of bits needed for |value| (the JPEG "SSSS" category)
+pub fn magnitude_category(value: i64) -> u8 {
+    (64 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+// JPEG's one's-complement style amplitude encoding for negative values
+pub fn amplitude_to_bits(value: i64) -> u32 {
+    let category = magnitude_category(value);
+    if value >= 0 {
+        value as u32
+    } else {
+        (value + (1i64 << category) - 1) as u32
+    }
+}
+
+// Inverse of `amplitude_to_bits` for a value of the given category
+pub fn amplitude_from_bits(bits: u32, category: u8) -> i64 {
+    if category == 0 {
+        0
+    } else if bits >= 1 << (category - 1) {
+        bits as i64
+    } else {
+        bits as i64 - (1i64 << category) + 1
+    }
+}
+
+// Quantized levels of a block read in zigzag order
+pub fn zigzag_levels(levels: &[Vec<i64>]) -> Vec<i64> {
+    matrix_ops::zigzag_indices(levels.len())
+        .into_iter()
+        .map(|(row, col)| levels[row][col])
+        .collect()
+}
+
+pub fn block_symbols(zigzag: &[i64], previous_dc: i64) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let dc_difference = zigzag[0] - previous_dc;
+    symbols.push(Symbol {
+        class: SymbolClass::Dc,
+        code: magnitude_category(dc_difference),
+        amplitude: dc_difference,
+    });
+
+    let mut run = 0;
+    for &level in &zigzag[1..] {
+        if level == 0 {
+            run += 1;
+            continue;
+        }
+        while run > MAX_RUN {
+            symbols.push(Symbol {
+                class: SymbolClass::Ac,
+                code: ZERO_RUN_LENGTH,
+                amplitude: 0,
+            });
+            run -= MAX_RUN + 1;
+        }
+        symbols.push(Symbol {
+            class: SymbolClass::Ac,
+            code: ((run as u8) << 4) | magnitude_category(level),
+            amplitude: level,
+        });
+        run = 0;
+    }
+
+    if run > 0 {
+        symbols.push(Symbol {
+            class: SymbolClass::Ac,
+            code: END_OF_BLOCK,
+            amplitude: 0,
+        });
+    }
+
+    symbols
+}
+
+// Symbols of every block, with the DC coded as the difference to the previous block
+pub fn image_symbols(blocks: &[Vec<Vec<i64>>]) -> Vec<Symbol> {
+    let mut previous_dc = 0;
+    let mut symbols = Vec::new();
+    for block in blocks {
+        let zigzag = zigzag_levels(block);
+        symbols.extend(block_symbols(&zigzag, previous_dc));
+        previous_dc = zigzag[0];
+    }
+    symbols
+}
+
+// Huffman codes and amplitude bits of `symbols`, MSB first, with the last byte
+// padded with 1 bits. JPEG scans also need `byte_stuffing` (a 0x00 after every 0xFF).
+pub fn encode_symbols(
+    symbols: &[Symbol],
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    byte_stuffing: bool,
+) -> Vec<u8> {
+    let dc_codes = dc_table.codes();
+    let ac_codes = ac_table.codes();
+    let mut writer = BitWriter {
+        byte_stuffing,
+        ..BitWriter::default()
+    };
+    for symbol in symbols {
+        let (code, length) = match symbol.class {
+            SymbolClass::Dc => dc_codes[symbol.code as usize],
+            SymbolClass::Ac => ac_codes[symbol.code as usize],
+        };
+        writer.write(code as u32, length as usize);
+        writer.write(amplitude_to_bits(symbol.amplitude), symbol.amplitude_bits());
+    }
+    writer.finish()
+}
+
+// Reverses `encode_symbols` (without byte stuffing) and `image_symbols`: the
+// levels of `block_count` blocks of `block_size`², or None if the data runs
+// out or holds a code the tables do not define
+pub fn decode_blocks(
+    data: &[u8],
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    block_size: usize,
+    block_count: usize,
+) -> Option<Vec<Vec<Vec<i64>>>> {
+    let dc_decoder = HuffmanDecoder::new(dc_table);
+    let ac_decoder = HuffmanDecoder::new(ac_table);
+    let zigzag_order = matrix_ops::zigzag_indices(block_size);
+    let coefficient_count = block_size * block_size;
+    let mut reader = BitReader { data, position: 0 };
+
+    let mut previous_dc = 0;
+    // Each block takes at least one bit, which bounds the count worth reserving
+    let mut blocks = Vec::with_capacity(block_count.min(data.len().saturating_mul(8)));
+    for _ in 0..block_count {
+        let mut zigzag = vec![0i64; coefficient_count];
+        let category = dc_decoder.decode(&mut reader).filter(|&category| category <= MAX_CATEGORY)?;
+        zigzag[0] = previous_dc + amplitude_from_bits(reader.read(category as usize)?, category);
+        previous_dc = zigzag[0];
+
+        let mut index = 1;
+        while index < coefficient_count {
+            let symbol = ac_decoder.decode(&mut reader)?;
+            match symbol {
+                END_OF_BLOCK => break,
+                ZERO_RUN_LENGTH => index += MAX_RUN + 1,
+                _ => {
+                    index += (symbol >> 4) as usize;
+                    let category = symbol & 0x0F;
+                    *zigzag.get_mut(index)? = amplitude_from_bits(reader.read(category as usize)?, category);
+                    index += 1;
+                }
+            }
+        }
+        if index > coefficient_count {
+            return None;
+        }
+
+        let mut levels = vec![vec![0i64; block_size]; block_size];
+        for (&(row, col), &level) in zigzag_order.iter().zip(zigzag.iter()) {
+            levels[row][col] = level;
+        }
+        blocks.push(levels);
+    }
+    Some(blocks)
+}
+
+// MSB-first bit packer
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u32,
+    pending_bits: usize,
+    byte_stuffing: bool,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: usize) {
+        for shift in (0..bits).rev() {
+            self.accumulator = (self.accumulator << 1) | ((value >> shift) & 1);
+            self.pending_bits += 1;
+            if self.pending_bits == 8 {
+                self.push_byte(self.accumulator as u8);
+                self.accumulator = 0;
+                self.pending_bits = 0;
+            }
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+        if self.byte_stuffing && byte == 0xFF {
+            self.bytes.push(0x00);
+        }
+    }
+
+    // Pads the last byte with 1 bits
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            let padding = 8 - self.pending_bits;
+            self.write((1 << padding) - 1, padding);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.position / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.position % 8)) & 1) as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+// Canonical decoding: the first code and first value index of every length
+struct HuffmanDecoder<'a> {
+    table: &'a HuffmanTable,
+    first_codes: [u32; MAX_CODE_LENGTH],
+    first_indices: [usize; MAX_CODE_LENGTH],
+}
+
+impl<'a> HuffmanDecoder<'a> {
+    fn new(table: &'a HuffmanTable) -> Self {
+        let mut first_codes = [0; MAX_CODE_LENGTH];
+        let mut first_indices = [0; MAX_CODE_LENGTH];
+        let (mut code, mut index) = (0u32, 0usize);
+        for (length, &count) in table.bits.iter().enumerate() {
+            first_codes[length] = code;
+            first_indices[length] = index;
+            code = (code + count as u32) << 1;
+            index += count as usize;
+        }
+        Self { table, first_codes, first_indices }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u8> {
+        let mut code = 0;
+        for length in 0..MAX_CODE_LENGTH {
+            code = (code << 1) | reader.read(1)?;
+            let offset = code.wrapping_sub(self.first_codes[length]);
+            if code >= self.first_codes[length] && offset < self.table.bits[length] as u32 {
+                return self.table.values.get(self.first_indices[length] + offset as usize).copied();
+            }
+        }
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HuffmanTable {
+    // bits[i] is the number of codes of length i + 1 (the JPEG BITS list)
+    pub bits: [u8; MAX_CODE_LENGTH],
+    // Symbols ordered by increasing code length (the JPEG HUFFVAL list)
+    pub values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    // JPEG Annex K.3 luminance AC table
+    pub fn standard_luminance_ac() -> Self {
+        Self {
+            bits: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+            values: STANDARD_LUMINANCE_AC_VALUES.to_vec(),
+        }
+    }
+
+    // Optimal length-limited table for the given symbol counts (JPEG Annex K.2)
+    pub fn from_frequencies(frequencies: &[u64; 256]) -> Self {
+        // Slot 256 is a reserved symbol so no real code is all 1-bits
+        let mut freq = [0u64; 257];
+        freq[..256].copy_from_slice(frequencies);
+        freq[256] = 1;
+        let mut code_size = [0usize; 257];
+        let mut others = [usize::MAX; 257];
+
+        while let Some(v1) = least_frequent(&freq, None) {
+            let Some(v2) = least_frequent(&freq, Some(v1)) else { break };
+
+            freq[v1] += freq[v2];
+            freq[v2] = 0;
+
+            let mut node = v1;
+            code_size[node] += 1;
+            while others[node] != usize::MAX {
+                node = others[node];
+                code_size[node] += 1;
+            }
+            others[node] = v2;
+
+            let mut node = v2;
+            code_size[node] += 1;
+            while others[node] != usize::MAX {
+                node = others[node];
+                code_size[node] += 1;
+            }
+        }
+
+        let mut counts = [0usize; 33];
+        for &size in code_size.iter().filter(|&&size| size > 0) {
+            counts[size.min(32)] += 1;
+        }
+
+        // Shorten codes longer than 16 bits while keeping the tree complete
+        for length in (MAX_CODE_LENGTH + 1..=32).rev() {
+            while counts[length] > 0 {
+                let mut shorter = length - 2;
+                while counts[shorter] == 0 {
+                    shorter -= 1;
+                }
+                counts[length] -= 2;
+                counts[length - 1] += 1;
+                counts[shorter + 1] += 2;
+                counts[shorter] -= 1;
+            }
+        }
+
+        // Drop the reserved symbol from the longest code length
+        let mut longest = MAX_CODE_LENGTH;
+        while longest > 0 && counts[longest] == 0 {
+            longest -= 1;
+        }
+        if longest > 0 {
+            counts[longest] -= 1;
+        }
+
+        let mut symbols_by_size: Vec<(usize, u8)> = (0..256)
+            .filter(|&symbol| code_size[symbol] > 0)
+            .map(|symbol| (code_size[symbol], symbol as u8))
+            .collect();
+        symbols_by_size.sort();
+
+        let mut bits = [0u8; MAX_CODE_LENGTH];
+        for (length, slot) in bits.iter_mut().enumerate() {
+            *slot = counts[length + 1] as u8;
+        }
+
+        Self {
+            bits,
+            values: symbols_by_size.into_iter().map(|(_, symbol)| symbol).collect(),
+        }
+    }
+
+    // Canonical (code, length) for each symbol; length 0 means the symbol has no code
+    pub fn codes(&self) -> Vec<(u16, u8)> {
+        let mut codes = vec![(0u16, 0u8); 256];
+        let mut code = 0u32;
+        let mut values = self.values.iter();
+        for (index, &count) in self.bits.iter().enumerate() {
+            for _ in 0..count {
+                if let Some(&symbol) = values.next() {
+                    codes[symbol as usize] = (code as u16, (index + 1) as u8);
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        codes
+    }
+
+    pub fn code_lengths(&self) -> Vec<u8> {
+        self.codes().into_iter().map(|(_, length)| length).collect()
+    }
+}
+
+fn least_frequent(freq: &[u64; 257], exclude: Option<usize>) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for (symbol, &count) in freq.iter().enumerate() {
+        if count == 0 || Some(symbol) == exclude {
+            continue;
+        }
+        // Ties go to the larger symbol value, as in the reference algorithm
+        if best.is_none_or(|current| count <= freq[current]) {
+            best = Some(symbol);
+        }
+    }
+    best
+}
+
+// Bit costs used by rate-distortion decisions, before the final tables are known
+pub struct HuffmanCostModel {
+    ac_lengths: Vec<u8>,
+}
+
+// Charged for symbols the table has no code for (e.g. categories above 10)
+const UNCODED_SYMBOL_BITS: usize = 16;
+
+impl HuffmanCostModel {
+    pub fn standard() -> Self {
+        Self::from_ac_table(&HuffmanTable::standard_luminance_ac())
+    }
+
+    pub fn from_ac_table(table: &HuffmanTable) -> Self {
+        Self {
+            ac_lengths: table.code_lengths(),
+        }
+    }
+
+    fn code_bits(&self, code: u8) -> usize {
+        match self.ac_lengths[code as usize] {
+            0 => UNCODED_SYMBOL_BITS,
+            length => length as usize,
+        }
+    }
+
+    // Bits for `level` after a run of `run` zeros, including any ZRL symbols
+    pub fn ac_bits(&self, run: usize, level: i64) -> usize {
+        let category = magnitude_category(level);
+        let zero_run_symbols = run / (MAX_RUN + 1);
+        let code = (((run % (MAX_RUN + 1)) as u8) << 4) | category;
+        zero_run_symbols * self.code_bits(ZERO_RUN_LENGTH) + self.code_bits(code) + category as usize
+    }
+
+    pub fn end_of_block_bits(&self) -> usize {
+        self.code_bits(END_OF_BLOCK)
+    }
+
+    // AC bits of a whole block, given its levels in zigzag order
+    pub fn block_ac_bits(&self, zigzag: &[i64]) -> usize {
+        block_symbols(zigzag, zigzag[0])
+            .iter()
+            .filter(|symbol| symbol.class == SymbolClass::Ac)
+            .map(|symbol| self.code_bits(symbol.code) + symbol.amplitude_bits())
+            .sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct EntropyCodedSize {
+    pub dc_bits: usize,
+    pub ac_bits: usize,
+    pub total_bits: usize,
+    pub total_bytes: usize,
+}
+
+// Exact size of the symbol stream coded with image-optimized Huffman tables,
+// i.e. what `jpegtran -optimize` would produce, excluding headers
+pub fn entropy_coded_size(blocks: &[Vec<Vec<i64>>]) -> EntropyCodedSize {
+    let symbols = image_symbols(blocks);
+    let (dc_table, ac_table) = optimized_tables(&symbols);
+    let dc_lengths = dc_table.code_lengths();
+    let ac_lengths = ac_table.code_lengths();
+
+    let mut dc_bits = 0;
+    let mut ac_bits = 0;
+    for symbol in &symbols {
+        match symbol.class {
+            SymbolClass::Dc => dc_bits += dc_lengths[symbol.code as usize] as usize + symbol.amplitude_bits(),
+            SymbolClass::Ac => ac_bits += ac_lengths[symbol.code as usize] as usize + symbol.amplitude_bits(),
+        }
+    }
+
+    EntropyCodedSize {
+        dc_bits,
+        ac_bits,
+        total_bits: dc_bits + ac_bits,
+        total_bytes: (dc_bits + ac_bits).div_ceil(8),
+    }
+}
+
+pub fn optimized_tables(symbols: &[Symbol]) -> (HuffmanTable, HuffmanTable) {
+    let mut dc_frequencies = [0u64; 256];
+    let mut ac_frequencies = [0u64; 256];
+    for symbol in symbols {
+        match symbol.class {
+            SymbolClass::Dc => dc_frequencies[symbol.code as usize] += 1,
+            SymbolClass::Ac => ac_frequencies[symbol.code as usize] += 1,
+        }
+    }
+    (
+        HuffmanTable::from_frequencies(&dc_frequencies),
+        HuffmanTable::from_frequencies(&ac_frequencies),
+    )
+}
+
+const STANDARD_LUMINANCE_AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_symbols_with_runs() {
+        let mut zigzag = vec![0i64; 64];
+        zigzag[0] = 5;
+        zigzag[1] = -3;
+        zigzag[20] = 1;
+
+        let symbols = block_symbols(&zigzag, 2);
+        let codes: Vec<u8> = symbols.iter().map(|symbol| symbol.code).collect();
+
+        // DC diff 3 -> category 2, then -3 (cat 2), ZRL, run 2 of 1 (cat 1), EOB
+        assert_eq!(codes, vec![0x02, 0x02, ZERO_RUN_LENGTH, 0x21, END_OF_BLOCK]);
+    }
+
+    #[test]
+    fn test_standard_ac_table_codes() {
+        let ac_codes = HuffmanTable::standard_luminance_ac().codes();
+        assert_eq!(ac_codes[END_OF_BLOCK as usize], (0b1010, 4));
+        assert_eq!(ac_codes[ZERO_RUN_LENGTH as usize], (0b11111111001, 11));
+        assert_eq!(ac_codes[0x01], (0b00, 2));
+    }
+
+    #[test]
+    fn test_optimized_table_limits_code_length() {
+        let mut frequencies = [0u64; 256];
+        // Fibonacci counts produce a maximally skewed tree deeper than 16 levels
+        let (mut a, mut b) = (1u64, 1u64);
+        for frequency in frequencies.iter_mut().take(30) {
+            *frequency = a;
+            (a, b) = (b, a + b);
+        }
+
+        let table = HuffmanTable::from_frequencies(&frequencies);
+        let lengths = table.code_lengths();
+
+        assert_eq!(table.values.len(), 30);
+        assert!(lengths.iter().all(|&length| length as usize <= MAX_CODE_LENGTH));
+        let kraft: f64 = lengths.iter().filter(|&&l| l > 0).map(|&l| 0.5f64.powi(l as i32)).sum();
+        assert!(kraft < 1.0);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::matrix_ops::MatrixError;
+use std::fmt;
+
+// Error type of the public API (`Encoder` and friends)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    // Empty, ragged or non-finite pixel data, or an image smaller than one block
+    InvalidImage(String),
+    // An option outside its valid range
+    InvalidParameter(String),
+    // Malformed or unsupported image file
+    Decode(String),
+    // Failure inside the compression pipeline
+    Compression(MatrixError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidImage(msg) => write!(f, "Invalid image: {}", msg),
+            Error::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
+            Error::Decode(msg) => write!(f, "Cannot decode image: {}", msg),
+            Error::Compression(error) => write!(f, "Image compression failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Compression(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<MatrixError> for Error {
+    fn from(error: MatrixError) -> Self {
+        match error {
+            MatrixError::InvalidParameter(msg) => Error::InvalidParameter(msg),
+            error => Error::Compression(error),
+        }
+    }
+}
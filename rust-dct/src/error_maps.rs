@@ -0,0 +1,177 @@
+use crate::matrix_ops::{Matrix, MatrixError};
+use crate::rgba_image::{gray_to_rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+// Pixels outside the area covered by whole blocks carry no error information
+const NO_DATA: [u8; 4] = [0, 0, 0, 0];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Colormap {
+    Grayscale,
+    #[default]
+    Hot,
+    Jet,
+    Viridis,
+}
+
+impl Colormap {
+    // Maps a normalized value in [0, 1] to an opaque RGBA color
+    pub fn color(&self, t: f64) -> [u8; 4] {
+        let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+        match self {
+            Colormap::Grayscale => gray_to_rgba(t * 255.0),
+            Colormap::Hot => to_rgba([
+                (t * 3.0).min(1.0),
+                (t * 3.0 - 1.0).clamp(0.0, 1.0),
+                (t * 3.0 - 2.0).clamp(0.0, 1.0),
+            ]),
+            Colormap::Jet => to_rgba([
+                (1.5 - (4.0 * t - 3.0).abs()).clamp(0.0, 1.0),
+                (1.5 - (4.0 * t - 2.0).abs()).clamp(0.0, 1.0),
+                (1.5 - (4.0 * t - 1.0).abs()).clamp(0.0, 1.0),
+            ]),
+            Colormap::Viridis => interpolate_stops(&VIRIDIS_STOPS, t),
+        }
+    }
+}
+
+// Five evenly spaced samples of matplotlib's viridis
+const VIRIDIS_STOPS: [[f64; 3]; 5] = [
+    [0.267, 0.005, 0.329],
+    [0.229, 0.322, 0.546],
+    [0.128, 0.567, 0.551],
+    [0.369, 0.789, 0.383],
+    [0.993, 0.906, 0.144],
+];
+
+fn interpolate_stops(stops: &[[f64; 3]], t: f64) -> [u8; 4] {
+    let position = t * (stops.len() - 1) as f64;
+    let lower = (position.floor() as usize).min(stops.len() - 2);
+    let fraction = position - lower as f64;
+
+    let mut rgb = [0.0; 3];
+    for (channel, value) in rgb.iter_mut().enumerate() {
+        *value = stops[lower][channel] + (stops[lower + 1][channel] - stops[lower][channel]) * fraction;
+    }
+    to_rgba(rgb)
+}
+
+fn to_rgba(rgb: [f64; 3]) -> [u8; 4] {
+    [
+        (rgb[0] * 255.0).round() as u8,
+        (rgb[1] * 255.0).round() as u8,
+        (rgb[2] * 255.0).round() as u8,
+        255,
+    ]
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ErrorMaps {
+    // compressed - original, mid-gray where they agree
+    pub difference_image: RgbaImage,
+    // |compressed - original| through the selected colormap
+    pub error_heatmap: RgbaImage,
+    // Sum of squared errors of each block, one pixel per block
+    pub block_error_map: RgbaImage,
+    pub max_absolute_error: f64,
+    pub max_block_error_energy: f64,
+}
+
+pub fn compute_error_maps(
+    original: &Matrix,
+    compressed: &Matrix,
+    block_size: usize,
+    colormap: Colormap,
+) -> Result<ErrorMaps, MatrixError> {
+    let height = original.len();
+    let width = original.first().map(|row| row.len()).unwrap_or(0);
+    if width == 0 || block_size == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if compressed.len() != height || compressed.iter().any(|row| row.len() != width) {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Compressed image must be {}x{} to match the original",
+            width, height
+        )));
+    }
+
+    let blocks_x = width / block_size;
+    let blocks_y = height / block_size;
+    let covered_width = blocks_x * block_size;
+    let covered_height = blocks_y * block_size;
+
+    let mut max_absolute_error = 0.0_f64;
+    let mut block_energy = vec![vec![0.0; blocks_x]; blocks_y];
+    for y in 0..covered_height {
+        for x in 0..covered_width {
+            let error = compressed[y][x] - original[y][x];
+            max_absolute_error = max_absolute_error.max(error.abs());
+            block_energy[y / block_size][x / block_size] += error * error;
+        }
+    }
+    let max_block_error_energy = block_energy.iter().flatten().fold(0.0_f64, |max, &e| max.max(e));
+
+    let mut difference_image = RgbaImage::new(width, height);
+    let mut error_heatmap = RgbaImage::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            if x >= covered_width || y >= covered_height {
+                difference_image.set_pixel(x, y, NO_DATA);
+                error_heatmap.set_pixel(x, y, NO_DATA);
+                continue;
+            }
+
+            let error = compressed[y][x] - original[y][x];
+            let normalized = if max_absolute_error > 0.0 { error / max_absolute_error } else { 0.0 };
+            difference_image.set_pixel(x, y, gray_to_rgba(127.5 + 127.5 * normalized));
+            error_heatmap.set_pixel(x, y, colormap.color(normalized.abs()));
+        }
+    }
+
+    let mut block_error_map = RgbaImage::new(blocks_x, blocks_y);
+    for (block_row, energies) in block_energy.iter().enumerate() {
+        for (block_col, &energy) in energies.iter().enumerate() {
+            let normalized = if max_block_error_energy > 0.0 { energy / max_block_error_energy } else { 0.0 };
+            block_error_map.set_pixel(block_col, block_row, colormap.color(normalized));
+        }
+    }
+
+    Ok(ErrorMaps {
+        difference_image,
+        error_heatmap,
+        block_error_map,
+        max_absolute_error,
+        max_block_error_energy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_maps_highlight_the_damaged_block() {
+        let original = vec![vec![100.0; 16]; 8];
+        let mut compressed = original.clone();
+        compressed[2][9] = 110.0;
+
+        let maps = compute_error_maps(&original, &compressed, 8, Colormap::Grayscale).unwrap();
+
+        assert_eq!(maps.max_absolute_error, 10.0);
+        assert_eq!((maps.block_error_map.width, maps.block_error_map.height), (2, 1));
+        assert_eq!(&maps.block_error_map.pixels[..4], &[0, 0, 0, 255]);
+        assert_eq!(&maps.block_error_map.pixels[4..], &[255, 255, 255, 255]);
+
+        let offset = (2 * 16 + 9) * 4;
+        assert_eq!(&maps.difference_image.pixels[offset..offset + 4], &[255, 255, 255, 255]);
+        assert_eq!(&maps.difference_image.pixels[..4], &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_colormap_endpoints() {
+        assert_eq!(Colormap::Hot.color(0.0), [0, 0, 0, 255]);
+        assert_eq!(Colormap::Hot.color(1.0), [255, 255, 255, 255]);
+        assert_eq!(Colormap::Jet.color(0.0), [0, 0, 128, 255]);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::coefficient_stats::quantized_levels;
+use crate::dct_compression::{self, quantize_dct_matrix, PIXEL_NORMALIZATION_OFFSET};
+use crate::entropy_coding::{self, HuffmanTable};
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use crate::quantizer::Quantizer;
+
+const JPEG_BLOCK_SIZE: usize = 8;
+// Baseline decoders add 128 after the inverse DCT
+const JPEG_LEVEL_SHIFT: f64 = 128.0;
+
+// Baseline JFIF file of a grayscale image, quantized with `table` (8x8, steps
+// rounded to 1–255) and Huffman tables optimized for the image. `image` must
+// already be padded to whole blocks; the frame header records `width` x `height`.
+pub fn encode_grayscale_jpeg(
+    image: &Matrix,
+    width: usize,
+    height: usize,
+    table: &Matrix,
+    quantizer: Quantizer,
+) -> Result<Vec<u8>, MatrixError> {
+    if table.len() != JPEG_BLOCK_SIZE || table.iter().any(|row| row.len() != JPEG_BLOCK_SIZE) {
+        return Err(MatrixError::IncompatibleDimensions(
+            "Baseline JPEG needs an 8x8 quantization table".to_string(),
+        ));
+    }
+    let blocks_x = width.div_ceil(JPEG_BLOCK_SIZE);
+    let blocks_y = height.div_ceil(JPEG_BLOCK_SIZE);
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(MatrixError::InvalidParameter(
+            "JPEG dimensions must be between 1 and 65535".to_string(),
+        ));
+    }
+    let covered = matrix_ops::crop(image, blocks_y * JPEG_BLOCK_SIZE, blocks_x * JPEG_BLOCK_SIZE);
+    if covered.len() != blocks_y * JPEG_BLOCK_SIZE || covered.iter().any(|row| row.len() != blocks_x * JPEG_BLOCK_SIZE) {
+        return Err(MatrixError::IncompatibleDimensions(
+            "Image must be padded to whole 8x8 blocks".to_string(),
+        ));
+    }
+
+    let table: Matrix = table
+        .iter()
+        .map(|row| row.iter().map(|step| step.round().clamp(1.0, 255.0)).collect())
+        .collect();
+    let (dct_matrices, _) = dct_compression::block_dct(&covered, JPEG_BLOCK_SIZE)?;
+    // Our blocks are centred on PIXEL_NORMALIZATION_OFFSET; move the DC to JPEG's level shift
+    let dc_correction = JPEG_BLOCK_SIZE as f64 * (JPEG_LEVEL_SHIFT - PIXEL_NORMALIZATION_OFFSET);
+    let levels = dct_matrices
+        .into_iter()
+        .map(|mut dct| {
+            dct[0][0] -= dc_correction;
+            Ok(quantized_levels(&quantize_dct_matrix(&dct, &table, quantizer)?, &table))
+        })
+        .collect::<Result<Vec<_>, MatrixError>>()?;
+
+    let symbols = entropy_coding::image_symbols(&levels);
+    let (dc_table, ac_table) = entropy_coding::optimized_tables(&symbols);
+
+    let mut output = vec![0xFF, 0xD8];
+    write_segment(&mut output, 0xE0, &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0]);
+
+    let mut quantization = vec![0x00];
+    quantization.extend(
+        matrix_ops::zigzag_indices(JPEG_BLOCK_SIZE)
+            .into_iter()
+            .map(|(row, col)| table[row][col] as u8),
+    );
+    write_segment(&mut output, 0xDB, &quantization);
+
+    let mut frame = vec![8];
+    frame.extend_from_slice(&(height as u16).to_be_bytes());
+    frame.extend_from_slice(&(width as u16).to_be_bytes());
+    frame.extend_from_slice(&[1, 1, 0x11, 0]);
+    write_segment(&mut output, 0xC0, &frame);
+
+    write_segment(&mut output, 0xC4, &huffman_segment(0x00, &dc_table));
+    write_segment(&mut output, 0xC4, &huffman_segment(0x10, &ac_table));
+    write_segment(&mut output, 0xDA, &[1, 1, 0x00, 0, 63, 0]);
+
+    output.extend(entropy_coding::encode_symbols(&symbols, &dc_table, &ac_table, true));
+    output.extend_from_slice(&[0xFF, 0xD9]);
+    Ok(output)
+}
+
+fn write_segment(output: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    output.extend_from_slice(payload);
+}
+
+fn huffman_segment(class_and_id: u8, table: &HuffmanTable) -> Vec<u8> {
+    let mut payload = vec![class_and_id];
+    payload.extend_from_slice(&table.bits);
+    payload.extend_from_slice(&table.values);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dct_compression::quantization_table;
+
+    #[test]
+    fn test_jpeg_structure() {
+        let image = matrix_ops::test_image(16, 16);
+
+        let jpeg = encode_grayscale_jpeg(&image, 13, 16, &quantization_table(), Quantizer::Nearest).unwrap();
+
+        assert_eq!(&jpeg[..4], &[0xFF, 0xD8, 0xFF, 0xE0]);
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xFF, 0xD9]);
+        let frame = jpeg.windows(2).position(|marker| marker == [0xFF, 0xC0]).unwrap();
+        assert_eq!(&jpeg[frame + 5..frame + 9], &[0, 16, 0, 13]);
+        assert!(encode_grayscale_jpeg(&image, 16, 16, &vec![vec![1.0; 4]; 4], Quantizer::Nearest).is_err());
+    }
+}
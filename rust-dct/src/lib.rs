@@ -1,131 +1,83 @@
-use wasm_bindgen::prelude::*;
-use serde_wasm_bindgen::{from_value, to_value};
-mod dct_compression;
-mod matrix_ops;
-
-use crate::dct_compression::CompressionResult;
-use crate::matrix_ops::Matrix;
-
-#[derive(Debug)]
-pub enum WasmError {
-    Deserialization(String),
-    Serialization(String),
-    Compression(String),
-}
-
-// Implementation to convert our custom error into a JavaScript error
-impl From<WasmError> for JsValue {
-    fn from(error: WasmError) -> JsValue {
-        let error_message = match error {
-            WasmError::Deserialization(msg) => format!("Failed to parse input data: {}", msg),
-            WasmError::Serialization(msg) => format!("Failed to prepare output data: {}", msg),
-            WasmError::Compression(msg) => format!("Image compression failed: {}", msg),
-        };
-        JsValue::from_str(&error_message)
-    }
-}
-
-// Type alias for our Result type to simplify error handling
-type WasmResult<T> = Result<T, WasmError>;
-
-#[wasm_bindgen(start)]
-pub fn initialize() {
-    // Set up panic hook for better error messages in the browser console
-    console_error_panic_hook::set_once();
-}
-
-#[wasm_bindgen]
-pub struct CompressionOptions {
-    width: usize,
-    height: usize,
-}
-
-#[wasm_bindgen]
-impl CompressionOptions {
-    #[wasm_bindgen(constructor)]
-    pub fn new(width: usize, height: usize) -> Self {
-        Self { width, height }
-    }
-}
-
-#[wasm_bindgen]
-pub struct ImageProcessor {
-    options: CompressionOptions,
-}
-
-#[wasm_bindgen]
-impl ImageProcessor {
-    #[wasm_bindgen(constructor)]
-    pub fn new(options: CompressionOptions) -> Self {
-        Self { options }
-    }
-
-    // Main compression function that processes the image data
-    pub fn compress_image(&self, image_data: JsValue) -> Result<JsValue, JsValue> {
-        self.process_compression(image_data)
-            .map_err(Into::into)
-    }
-
-    // Internal helper function to handle the actual compression logic
-    fn process_compression(&self, image_data: JsValue) -> WasmResult<JsValue> {
-        // Convert JavaScript array into Rust Matrix type
-        let image_matrix: Matrix = from_value(image_data)
-            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
-
-        // Validate image dimensions
-        self.validate_dimensions(&image_matrix)?;
-
-        // Perform the DCT compression
-        let compression_result = dct_compression::compress_image_dct(
-            image_matrix,
-            self.options.width,
-            self.options.height,
-        ).map_err(|e| WasmError::Compression(e.to_string()))?;
-
-        // Convert the result back to JavaScript
-        self.serialize_result(compression_result)
-    }
-
-    // Validation helper to ensure image dimensions are correct
-    fn validate_dimensions(&self, image: &Matrix) -> WasmResult<()> {
-        let actual_height = image.len();
-        let actual_width = image.first()
-            .map(|row| row.len())
-            .unwrap_or(0);
-
-        if actual_height != self.options.height || actual_width != self.options.width {
-            return Err(WasmError::Deserialization(format!(
-                "Image dimensions mismatch. Expected {}x{}, got {}x{}",
-                self.options.width, self.options.height,
-                actual_width, actual_height
-            )));
-        }
-        Ok(())
-    }
-
-    // Serialization helper to convert Rust types to JavaScript
-    fn serialize_result(&self, result: CompressionResult) -> WasmResult<JsValue> {
-        to_value(&result)
-            .map_err(|e| WasmError::Serialization(e.to_string()))
-    }
-}
-
-// JavaScript usage example (in comments for documentation)
-/*
-// JavaScript code:
-import init, { ImageProcessor, CompressionOptions } from './pkg/your_module';
-
-async function compressImage(imageData, width, height) {
-    await init();
-    
-    const options = new CompressionOptions(width, height);
-    const processor = new ImageProcessor(options);
-    
-    try {
-        const result = processor.compress_image(imageData);
-        console.log('Compression successful:', result);
-    } catch (error) {
-        console.error('Compression failed:', error);
-    }
+// DCT image compression pipeline. `Encoder` is the entry point for Rust
+// callers; the browser demo goes through the `wasm` bindings. File formats
+// are public modules, the supported types are re-exported below and the
+// rest is internal.
+pub(crate) mod adaptive_quantization;
+pub(crate) mod block_export;
+pub(crate) mod coefficient_mask;
+pub(crate) mod coefficient_stats;
+pub(crate) mod dct_basis;
+pub(crate) mod dct_compression;
+pub mod dctz;
+pub(crate) mod deblocking;
+pub(crate) mod encoder;
+pub(crate) mod energy_compaction;
+pub(crate) mod entropy_coding;
+pub(crate) mod error;
+pub(crate) mod error_maps;
+pub mod jpeg_writer;
+pub(crate) mod matrix_ops;
+pub mod npy;
+pub(crate) mod parallel;
+pub(crate) mod perceptual_table;
+pub(crate) mod planar_image;
+pub mod png;
+pub mod pnm;
+pub(crate) mod pocs;
+pub(crate) mod progressive;
+pub(crate) mod quality_metrics;
+pub(crate) mod quality_scaling;
+pub(crate) mod quantizer;
+pub(crate) mod region_of_interest;
+pub(crate) mod rgba_image;
+pub(crate) mod simd;
+pub(crate) mod table_optimizer;
+pub(crate) mod trellis;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub(crate) mod zlib;
+
+pub use crate::adaptive_quantization::{ActivityMetric, AdaptiveQuantization};
+pub use crate::block_export::{BlockMatrixKind, BLOCK_EXPORT_SCHEMA_VERSION};
+pub use crate::coefficient_mask::{reconstruct_masked_blocks, CoefficientMask, MaskedBlock};
+pub use crate::coefficient_stats::{CoefficientStatistics, HistogramBin};
+pub use crate::dct_basis::generate_basis_image;
+pub use crate::dct_compression::{
+    compress_image_dct, AnalysisStage, CompressionConfig, CompressionJob, CompressionProgress, CompressionResult,
+    QuantizationTableSource, QuantizerReport, TableComparison,
+};
+pub use crate::deblocking::{DeblockingFilter, DeblockingResult};
+pub use crate::encoder::{Compressed, Encoder};
+pub use crate::energy_compaction::{BlockEnergyCompaction, EnergyCompaction, DEFAULT_COMPACTION_COEFFICIENTS};
+pub use crate::entropy_coding::EntropyCodedSize;
+pub use crate::error::{Error, Result};
+pub use crate::error_maps::{Colormap, ErrorMaps};
+pub use crate::matrix_ops::{EdgeMode, Matrix, MatrixError};
+pub use crate::perceptual_table::ViewingConditions;
+pub use crate::planar_image::PlanarImage;
+pub use crate::pocs::{PocsResult, PocsSmoothing, DEFAULT_POCS_SMOOTHING};
+pub use crate::progressive::{
+    progressive_block_frames, progressive_image_frames, CoefficientOrder, ProgressiveImageFrames,
+};
+pub use crate::quality_metrics::QualityMetrics;
+pub use crate::quality_scaling::DEFAULT_QUALITY;
+pub use crate::quantizer::Quantizer;
+pub use crate::region_of_interest::{
+    RegionOfInterest, RegionOfInterestReport, DEFAULT_ROI_BACKGROUND_SCALE, DEFAULT_ROI_FOREGROUND_SCALE,
+};
+pub use crate::rgba_image::RgbaImage;
+pub use crate::table_optimizer::{
+    optimize_quantization_table, OptimizedTable, QualityTarget, TableOptimization, DEFAULT_MAX_PASSES,
+};
+pub use crate::trellis::{TrellisBlockDecision, TrellisReport};
+
+// Pipeline stages timed one by one in `benches/pipeline.rs`
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::dct_compression::{
+        block_dct, calculate_dct_coefficients, generate_mathml_documentation, quantization_table,
+        quantize_dct_matrix, reconstruct_image_block, PIXEL_NORMALIZATION_OFFSET,
+    };
+    pub use crate::matrix_ops::{multiply, partition_into_blocks, transpose};
 }
-*/
\ No newline at end of file
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 
@@ -7,6 +8,8 @@ pub type Matrix = Vec<Vec<f64>>;
 pub enum MatrixError {
     IncompatibleDimensions(String),
     EmptyMatrix,
+    BlockIndexOutOfRange { index: usize, block_count: usize },
+    InvalidParameter(String),
 }
 
 impl fmt::Display for MatrixError {
@@ -14,6 +17,12 @@ impl fmt::Display for MatrixError {
         match self {
             MatrixError::IncompatibleDimensions(msg) => write!(f, "Incompatible matrix dimensions: {}", msg),
             MatrixError::EmptyMatrix => write!(f, "Operation cannot be performed on empty matrix"),
+            MatrixError::BlockIndexOutOfRange { index, block_count } => write!(
+                f,
+                "Block index {} is out of range for an image with {} blocks",
+                index, block_count
+            ),
+            MatrixError::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
         }
     }
 }
@@ -61,9 +70,8 @@ pub fn partition_into_blocks(matrix: &Matrix, block_size: usize) -> Result<Vec<M
 
 fn extract_block(matrix: &Matrix, start_row: usize, start_col: usize, size: usize) -> Matrix {
     let mut block = Vec::with_capacity(size);
-    for row in start_row..start_row + size {
-        let block_row: Vec<f64> = matrix[row][start_col..start_col + size].to_vec();
-        block.push(block_row);
+    for row in matrix.iter().skip(start_row).take(size) {
+        block.push(row[start_col..start_col + size].to_vec());
     }
     block
 }
@@ -110,6 +118,23 @@ pub fn transpose(matrix: &Matrix) -> Result<Matrix, MatrixError> {
     Ok(transposed)
 }
 
+// Side of blocks that are all square and of the same size
+pub fn square_block_size(blocks: &[Matrix]) -> Result<usize, MatrixError> {
+    let block_size = blocks.first().ok_or(MatrixError::EmptyMatrix)?.len();
+    if block_size == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if blocks
+        .iter()
+        .any(|block| block.len() != block_size || block.iter().any(|row| row.len() != block_size))
+    {
+        return Err(MatrixError::IncompatibleDimensions(
+            "Blocks must all be square and of the same size".to_string(),
+        ));
+    }
+    Ok(block_size)
+}
+
 pub fn merge_blocks(
     target: &mut Matrix,
     block: &Matrix,
@@ -117,7 +142,17 @@ pub fn merge_blocks(
     block_size: usize,
 ) -> Result<(), MatrixError> {
     let target_dims = MatrixDimensions::new(target)?;
+    if block_size == 0 || block.len() != block_size || block.iter().any(|row| row.len() != block_size) {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Block is not {}x{}",
+            block_size, block_size
+        )));
+    }
     let blocks_per_row = target_dims.cols / block_size;
+    let block_count = blocks_per_row * (target_dims.rows / block_size);
+    if block_index >= block_count {
+        return Err(MatrixError::BlockIndexOutOfRange { index: block_index, block_count });
+    }
 
     let start_col = (block_index % blocks_per_row) * block_size;
     let start_row = (block_index / blocks_per_row) * block_size;
@@ -131,6 +166,92 @@ pub fn merge_blocks(
     Ok(())
 }
 
+// Top-left `rows` x `cols` region, e.g. the part of an image covered by whole blocks
+pub fn crop(matrix: &Matrix, rows: usize, cols: usize) -> Matrix {
+    matrix
+        .iter()
+        .take(rows)
+        .map(|row| row.iter().take(cols).copied().collect())
+        .collect()
+}
+
+// How to deal with images whose sides are not a multiple of the block size
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeMode {
+    // Drop the partial blocks
+    Crop,
+    // Pad by repeating the last row/column
+    #[default]
+    Replicate,
+    // Pad by reflecting around the last row/column (without repeating it)
+    Mirror,
+    // Pad with zeros
+    Zero,
+}
+
+// Grows (or, for `EdgeMode::Crop`, shrinks) the matrix to whole blocks
+pub fn fit_to_blocks(matrix: &Matrix, block_size: usize, mode: EdgeMode) -> Result<Matrix, MatrixError> {
+    let dims = MatrixDimensions::new(matrix)?;
+    if block_size == 0 {
+        return Err(MatrixError::InvalidParameter("Block size must be positive".to_string()));
+    }
+    if mode == EdgeMode::Crop {
+        return Ok(crop(matrix, dims.rows / block_size * block_size, dims.cols / block_size * block_size));
+    }
+
+    let rows = dims.rows.div_ceil(block_size) * block_size;
+    let cols = dims.cols.div_ceil(block_size) * block_size;
+    // Source index for a padded position; reflection repeats with period 2(n - 1)
+    let source = |index: usize, size: usize| -> Option<usize> {
+        if index < size {
+            return Some(index);
+        }
+        match mode {
+            EdgeMode::Mirror if size > 1 => {
+                let period = 2 * (size - 1);
+                let phase = index % period;
+                Some(if phase < size { phase } else { period - phase })
+            }
+            EdgeMode::Zero => None,
+            _ => Some(size - 1),
+        }
+    };
+
+    Ok((0..rows)
+        .map(|i| {
+            (0..cols)
+                .map(|j| match (source(i, dims.rows), source(j, dims.cols)) {
+                    (Some(row), Some(col)) => matrix[row][col],
+                    _ => 0.0,
+                })
+                .collect()
+        })
+        .collect())
+}
+
+// Visits the (row, col) positions of a square block in JPEG zigzag order,
+// walking the anti-diagonals from the DC coefficient outwards
+pub fn zigzag_indices(size: usize) -> Vec<(usize, usize)> {
+    let mut indices = Vec::with_capacity(size * size);
+
+    for diagonal in 0..(2 * size).saturating_sub(1) {
+        let first_row = diagonal.saturating_sub(size - 1);
+        let last_row = diagonal.min(size - 1);
+        let rows: Vec<usize> = if diagonal % 2 == 0 {
+            (first_row..=last_row).rev().collect()
+        } else {
+            (first_row..=last_row).collect()
+        };
+
+        for row in rows {
+            indices.push((row, diagonal - row));
+        }
+    }
+
+    indices
+}
+
 // pub fn to_latex(matrix: &Matrix) -> Result<String, MatrixError> {
 //     let dims = MatrixDimensions::new(matrix)?;
 //     let mut latex = String::from("\\begin{bmatrix}\n");
@@ -169,6 +290,14 @@ pub fn to_mathml(matrix: &Matrix) -> Result<String, MatrixError> {
     Ok(mathml)
 }
 
+// Busy but deterministic test image, with values in 64..=160
+#[cfg(test)]
+pub(crate) fn test_image(width: usize, height: usize) -> Matrix {
+    (0..height)
+        .map(|y| (0..width).map(|x| ((x * 29 + y * 17) % 97 + 64) as f64).collect())
+        .collect()
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -182,10 +311,43 @@ mod tests {
         assert_eq!(result, vec![vec![19.0, 22.0], vec![43.0, 50.0]]);
     }
 
+    #[test]
+    fn test_merge_blocks_rejects_blocks_outside_the_target() {
+        let mut target = vec![vec![0.0; 5]; 4];
+        let block = vec![vec![1.0; 2]; 2];
+        merge_blocks(&mut target, &block, 3, 2).unwrap();
+        assert_eq!((target[3][3], target[3][4]), (1.0, 0.0));
+
+        assert!(matches!(
+            merge_blocks(&mut target, &block, 4, 2),
+            Err(MatrixError::BlockIndexOutOfRange { index: 4, block_count: 4 })
+        ));
+        assert!(merge_blocks(&mut target, &block, 0, 0).is_err());
+        assert!(merge_blocks(&mut target, &block, 0, 3).is_err());
+        assert!(merge_blocks(&mut target, &vec![vec![1.0; 6]; 6], 0, 6).is_err());
+    }
+
     #[test]
     fn test_matrix_transpose() {
         let matrix = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
         let transposed = transpose(&matrix).unwrap();
         assert_eq!(transposed, vec![vec![1.0, 3.0], vec![2.0, 4.0]]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_zigzag_indices() {
+        let indices = zigzag_indices(8);
+        assert_eq!(indices.len(), 64);
+        assert_eq!(&indices[..6], &[(0, 0), (0, 1), (1, 0), (2, 0), (1, 1), (0, 2)]);
+        assert_eq!(indices[63], (7, 7));
+    }
+
+    #[test]
+    fn test_fit_to_blocks() {
+        let matrix = vec![vec![1.0, 2.0, 3.0]];
+        assert_eq!(fit_to_blocks(&matrix, 2, EdgeMode::Replicate).unwrap(), vec![vec![1.0, 2.0, 3.0, 3.0]; 2]);
+        assert_eq!(fit_to_blocks(&matrix, 2, EdgeMode::Mirror).unwrap()[0], vec![1.0, 2.0, 3.0, 2.0]);
+        assert_eq!(fit_to_blocks(&matrix, 2, EdgeMode::Zero).unwrap()[1], vec![0.0; 4]);
+        assert!(fit_to_blocks(&matrix, 2, EdgeMode::Crop).unwrap().is_empty());
+    }
+}
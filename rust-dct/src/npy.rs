@@ -0,0 +1,56 @@
+// NumPy `.npy` (format 1.0) writer for float64 arrays, so results load with
+// `numpy.load` without any JSON parsing
+use crate::matrix_ops::Matrix;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+// Header (magic, version, length and dict) is padded to a multiple of this
+const HEADER_ALIGNMENT: usize = 64;
+
+// C-order array of little-endian f64 with the given shape; `values.len()`
+// must equal the product of `shape`
+pub fn encode(shape: &[usize], values: impl IntoIterator<Item = f64>) -> Vec<u8> {
+    let shape_text = match shape {
+        [length] => format!("({},)", length),
+        _ => format!("({})", shape.iter().map(|dimension| dimension.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}", shape_text);
+    let prefix_length = MAGIC.len() + 2 + 2;
+    let padding = HEADER_ALIGNMENT - (prefix_length + header.len() + 1) % HEADER_ALIGNMENT;
+    header.push_str(&" ".repeat(padding % HEADER_ALIGNMENT));
+    header.push('\n');
+
+    let mut output = MAGIC.to_vec();
+    output.extend_from_slice(&[1, 0]);
+    output.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    output.extend_from_slice(header.as_bytes());
+    for value in values {
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+    output
+}
+
+// 2-D array of shape (rows, cols)
+pub fn encode_matrix(matrix: &Matrix) -> Vec<u8> {
+    let cols = matrix.first().map_or(0, |row| row.len());
+    encode(&[matrix.len(), cols], matrix.iter().flatten().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_layout() {
+        let npy = encode_matrix(&vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = std::str::from_utf8(&npy[10..10 + header_length]).unwrap();
+
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!((10 + header_length) % HEADER_ALIGNMENT, 0);
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(npy.len(), 10 + header_length + 6 * 8);
+        assert_eq!(&npy[npy.len() - 8..], &6.0f64.to_le_bytes());
+        assert!(std::str::from_utf8(&encode(&[4], [0.0; 4])[10..64]).unwrap().contains("'shape': (4,)"));
+    }
+}
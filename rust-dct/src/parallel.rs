@@ -0,0 +1,34 @@
+// Order-preserving map over block indices: rayon's thread pool with the
+// `parallel` feature, a plain loop otherwise. Results always come back in
+// index order, so the output does not depend on the number of threads.
+use std::ops::Range;
+
+#[cfg(feature = "parallel")]
+pub fn map_blocks<R, F>(blocks: Range<usize>, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(usize) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+    blocks.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map_blocks<R, F>(blocks: Range<usize>, f: F) -> Vec<R>
+where
+    F: Fn(usize) -> R,
+{
+    blocks.map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_keep_block_order() {
+        let squares = map_blocks(3..1003, |index| index * index);
+        assert_eq!(squares.len(), 1000);
+        assert!(squares.iter().zip(3..).all(|(&square, index)| square == index * index));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::matrix_ops::{Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+
+// Smallest step in the Annex K luminance table
+pub const DEFAULT_PEAK_STEP: f64 = 10.0;
+// Mannos–Sakrison sensitivity peaks around 8 cycles per degree; lower
+// frequencies are treated as equally visible, as is usual for image coding
+const PEAK_FREQUENCY: f64 = 8.0;
+const MAX_STEP: f64 = 255.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ViewingConditions {
+    // Eye-to-screen distance in inches, matching the DPI unit
+    pub viewing_distance: f64,
+    pub display_dpi: f64,
+    pub block_size: usize,
+    // Step given to the most visible frequencies
+    #[serde(default = "default_peak_step")]
+    pub peak_step: f64,
+}
+
+fn default_peak_step() -> f64 {
+    DEFAULT_PEAK_STEP
+}
+
+impl ViewingConditions {
+    fn validate(&self) -> Result<(), MatrixError> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        if !positive(self.viewing_distance) || !positive(self.display_dpi) || !positive(self.peak_step) {
+            return Err(MatrixError::InvalidParameter(
+                "Viewing distance, display DPI and peak step must be positive numbers".to_string(),
+            ));
+        }
+        if self.block_size < 2 {
+            return Err(MatrixError::InvalidParameter(
+                "Perceptual tables need a block size of at least 2".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Pixels covered by one degree of visual angle
+    pub fn pixels_per_degree(&self) -> f64 {
+        2.0 * self.viewing_distance * self.display_dpi * 0.5f64.to_radians().tan()
+    }
+}
+
+// Mannos & Sakrison (1974) contrast sensitivity at `frequency` cycles per degree,
+// normalized to 1 at (and below) the peak
+pub fn contrast_sensitivity(frequency: f64) -> f64 {
+    let mannos_sakrison = |f: f64| 2.6 * (0.0192 + 0.114 * f) * (-(0.114 * f).powf(1.1)).exp();
+    if frequency <= PEAK_FREQUENCY {
+        1.0
+    } else {
+        mannos_sakrison(frequency) / mannos_sakrison(PEAK_FREQUENCY)
+    }
+}
+
+// Radial frequency of DCT basis function (u, v) in cycles per degree: basis
+// u completes u/2 cycles across the N pixels of a block
+pub fn basis_frequency(u: usize, v: usize, block_size: usize, pixels_per_degree: f64) -> f64 {
+    let cycles_per_pixel = ((u * u + v * v) as f64).sqrt() / (2 * block_size) as f64;
+    cycles_per_pixel * pixels_per_degree
+}
+
+// Step for every coefficient inversely proportional to how visible its basis
+// pattern is under the given viewing conditions
+pub fn generate_perceptual_table(conditions: ViewingConditions) -> Result<Matrix, MatrixError> {
+    conditions.validate()?;
+    let pixels_per_degree = conditions.pixels_per_degree();
+    let size = conditions.block_size;
+
+    Ok((0..size)
+        .map(|u| {
+            (0..size)
+                .map(|v| {
+                    let sensitivity = contrast_sensitivity(basis_frequency(u, v, size, pixels_per_degree));
+                    (conditions.peak_step / sensitivity).round().clamp(1.0, MAX_STEP)
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_grow_with_frequency_and_distance() {
+        let near = ViewingConditions {
+            viewing_distance: 12.0,
+            display_dpi: 96.0,
+            block_size: 8,
+            peak_step: DEFAULT_PEAK_STEP,
+        };
+        let far = ViewingConditions { viewing_distance: 48.0, ..near };
+
+        let near_table = generate_perceptual_table(near).unwrap();
+        let far_table = generate_perceptual_table(far).unwrap();
+
+        assert_eq!(near_table[0][0], DEFAULT_PEAK_STEP);
+        assert!(near_table[7][7] > near_table[3][3]);
+        assert!(far_table[7][7] > near_table[7][7]);
+        assert_eq!(near_table[2][5], near_table[5][2]);
+        assert!(generate_perceptual_table(ViewingConditions { block_size: 1, ..near }).is_err());
+    }
+}
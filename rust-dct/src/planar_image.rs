@@ -0,0 +1,94 @@
+use crate::error::{Error, Result};
+use crate::matrix_ops::Matrix;
+use crate::{png, pnm, simd};
+
+pub const MAX_MAXVAL: u32 = 65535;
+
+// Planar image with samples in 0..=maxval; 1 plane is grayscale, 2 gray +
+// alpha, 3 RGB and 4 RGBA
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanarImage {
+    pub width: usize,
+    pub height: usize,
+    pub maxval: u32,
+    pub planes: Vec<Matrix>,
+}
+
+impl PlanarImage {
+    // PNG or PNM (PGM/PPM/PAM) file contents, told apart by the signature
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(png::PNG_SIGNATURE) {
+            png::decode(bytes)
+        } else {
+            pnm::decode(bytes)
+        }
+    }
+
+    // 8-bit grayscale image from a 0–255 matrix (values rounded and clamped)
+    pub fn from_matrix(matrix: &Matrix) -> Self {
+        Self::from_planes(vec![matrix.clone()])
+    }
+
+    // 8-bit image from 0–255 planes (values rounded and clamped)
+    pub fn from_planes(planes: Vec<Matrix>) -> Self {
+        let height = planes.first().map_or(0, |plane| plane.len());
+        let width = planes.first().and_then(|plane| plane.first()).map_or(0, |row| row.len());
+        Self {
+            width,
+            height,
+            maxval: 255,
+            planes: planes
+                .into_iter()
+                .map(|plane| {
+                    plane
+                        .into_iter()
+                        .map(|row| row.into_iter().map(|value| value.round().clamp(0.0, 255.0)).collect())
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    // Planes rescaled to 0–255
+    pub fn planes_8bit(&self) -> Vec<Matrix> {
+        let scale = 255.0 / self.maxval as f64;
+        self.planes
+            .iter()
+            .map(|plane| plane.iter().map(|row| row.iter().map(|value| value * scale).collect()).collect())
+            .collect()
+    }
+
+    // 0–255 luma; color uses the ITU-R BT.601 weights of JPEG/JFIF, alpha is ignored
+    pub fn to_luma(&self) -> Matrix {
+        let planes = self.planes_8bit();
+        match planes.len() {
+            3 | 4 => (0..self.height)
+                .map(|y| {
+                    let mut row = vec![0.0; self.width];
+                    simd::luma_row(&planes[0][y], &planes[1][y], &planes[2][y], &mut row);
+                    row
+                })
+                .collect(),
+            _ => planes.into_iter().next().unwrap_or_default(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=4).contains(&self.planes.len()) {
+            return Err(Error::InvalidImage(format!("{} planes, expected 1 to 4", self.planes.len())));
+        }
+        if !(1..=MAX_MAXVAL).contains(&self.maxval) {
+            return Err(Error::InvalidImage(format!("Maxval {} is out of range", self.maxval)));
+        }
+        let consistent = self.planes.iter().all(|plane| {
+            plane.len() == self.height && plane.iter().all(|row| row.len() == self.width)
+        });
+        if !consistent || self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidImage(format!(
+                "Every plane must be a non-empty {}x{} matrix",
+                self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+use crate::error::{Error, Result};
+use crate::matrix_ops::Matrix;
+use crate::planar_image::PlanarImage;
+use crate::zlib;
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Adam7 passes as (x offset, y offset, x step, y step)
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorType {
+    Gray,
+    Rgb,
+    Palette,
+    GrayAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ColorType::Gray),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Palette),
+            4 => Some(ColorType::GrayAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    fn channels(self) -> usize {
+        match self {
+            ColorType::Gray | ColorType::Palette => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    fn allows_bit_depth(self, bit_depth: u8) -> bool {
+        match self {
+            ColorType::Gray => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Palette => matches!(bit_depth, 1 | 2 | 4 | 8),
+            _ => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlaced: bool,
+}
+
+// Decodes any standard PNG. Palette images are expanded to RGB (RGBA with a
+// tRNS chunk); gray and RGB images with a tRNS color key gain an alpha plane.
+// `maxval` is 2^depth - 1 of the file, 255 for palette images.
+pub fn decode(bytes: &[u8]) -> Result<PlanarImage> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(Error::Decode("Missing PNG signature".to_string()));
+    }
+
+    let mut header = None;
+    let mut palette: Vec<[u32; 3]> = Vec::new();
+    let mut transparency: Option<Vec<u8>> = None;
+    let mut compressed = Vec::new();
+    let mut position = PNG_SIGNATURE.len();
+    loop {
+        let (chunk_type, data) = read_chunk(bytes, &mut position)?;
+        match &chunk_type {
+            b"IHDR" => header = Some(read_header(data)?),
+            b"PLTE" => {
+                if data.len() % 3 != 0 || data.len() > 3 * 256 {
+                    return Err(Error::Decode("Invalid PNG palette".to_string()));
+                }
+                palette = data
+                    .chunks(3)
+                    .map(|entry| [entry[0] as u32, entry[1] as u32, entry[2] as u32])
+                    .collect();
+            }
+            b"tRNS" => transparency = Some(data.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            // Unknown critical chunks (uppercase first letter) change how the image decodes
+            _ if chunk_type[0].is_ascii_uppercase() => {
+                return Err(Error::Decode(format!(
+                    "Unsupported critical PNG chunk {}",
+                    String::from_utf8_lossy(&chunk_type)
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| Error::Decode("PNG has no IHDR chunk".to_string()))?;
+    if header.color_type == ColorType::Palette && palette.is_empty() {
+        return Err(Error::Decode("Palette PNG has no PLTE chunk".to_string()));
+    }
+    // The header's dimensions are only trusted once the data matches them
+    let filtered_length = header.filtered_length()?;
+    let raw = zlib::decompress(&compressed, filtered_length)?;
+    if raw.len() != filtered_length {
+        return Err(Error::Decode(format!(
+            "PNG image data has {} bytes, expected {} for {}x{}",
+            raw.len(),
+            filtered_length,
+            header.width,
+            header.height
+        )));
+    }
+    let samples = unfilter_image(&raw, &header)?;
+
+    let (width, height) = (header.width, header.height);
+    let channels = header.color_type.channels();
+    let plane = |channel: usize| -> Matrix {
+        (0..height)
+            .map(|y| (0..width).map(|x| samples[(y * width + x) * channels + channel] as f64).collect())
+            .collect()
+    };
+    let maxval = (1u32 << header.bit_depth) - 1;
+
+    if header.color_type == ColorType::Palette {
+        let alpha = transparency.unwrap_or_default();
+        let mut planes = vec![vec![vec![0.0; width]; height]; if alpha.is_empty() { 3 } else { 4 }];
+        for y in 0..height {
+            for x in 0..width {
+                let index = samples[y * width + x] as usize;
+                let entry = palette
+                    .get(index)
+                    .ok_or_else(|| Error::Decode("PNG palette index out of range".to_string()))?;
+                for channel in 0..3 {
+                    planes[channel][y][x] = entry[channel] as f64;
+                }
+                if let Some(alpha_plane) = planes.get_mut(3) {
+                    alpha_plane[y][x] = alpha.get(index).map_or(255.0, |&value| value as f64);
+                }
+            }
+        }
+        return Ok(PlanarImage { width, height, maxval: 255, planes });
+    }
+
+    let mut planes: Vec<Matrix> = (0..channels).map(plane).collect();
+    if let (Some(key), ColorType::Gray | ColorType::Rgb) = (transparency, header.color_type) {
+        // The color key holds one 16-bit sample per channel
+        let key: Vec<f64> = key
+            .chunks(2)
+            .take(channels)
+            .map(|sample| sample.iter().fold(0, |value, &byte| (value << 8) | byte as u32) as f64)
+            .collect();
+        if key.len() == channels {
+            let alpha = (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| {
+                            let transparent = (0..channels).all(|channel| planes[channel][y][x] == key[channel]);
+                            if transparent {
+                                0.0
+                            } else {
+                                maxval as f64
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            planes.push(alpha);
+        }
+    }
+    Ok(PlanarImage { width, height, maxval, planes })
+}
+
+// Non-interlaced PNG of 1 to 4 planes (gray, gray + alpha, RGB, RGBA). Images
+// with maxval up to 255 are stored with 8 bits per sample, others with 16; other
+// maxvals are rescaled to the full range. Each row uses the filter whose output
+// has the smallest sum of absolute values.
+pub fn encode(image: &PlanarImage) -> Result<Vec<u8>> {
+    image.validate()?;
+    if image.width > i32::MAX as usize || image.height > i32::MAX as usize {
+        return Err(Error::InvalidImage("PNG dimensions must fit in 31 bits".to_string()));
+    }
+    let color_type: u8 = match image.planes.len() {
+        1 => 0,
+        2 => 4,
+        3 => 2,
+        _ => 6,
+    };
+    let (bit_depth, target) = if image.maxval <= 255 { (8u8, 255.0) } else { (16u8, 65535.0) };
+    let scale = target / image.maxval as f64;
+    let sample_bytes = bit_depth as usize / 8;
+    let pixel_bytes = image.planes.len() * sample_bytes;
+    let row_bytes = image.width * pixel_bytes;
+
+    let mut filtered = Vec::with_capacity((row_bytes + 1) * image.height);
+    let mut previous = vec![0u8; row_bytes];
+    for y in 0..image.height {
+        let mut row = Vec::with_capacity(row_bytes);
+        for x in 0..image.width {
+            for plane in &image.planes {
+                let sample = (plane[y][x] * scale).round().clamp(0.0, target) as u16;
+                if bit_depth == 8 {
+                    row.push(sample as u8);
+                } else {
+                    row.extend_from_slice(&sample.to_be_bytes());
+                }
+            }
+        }
+
+        let (filter, bytes) = (0..5u8)
+            .map(|filter| (filter, filter_row(filter, &row, &previous, pixel_bytes)))
+            .min_by_key(|(_, bytes)| bytes.iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum::<u64>())
+            .expect("five filters");
+        filtered.push(filter);
+        filtered.extend(bytes);
+        previous = row;
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+    let mut output = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut output, b"IHDR", &ihdr);
+    write_chunk(&mut output, b"IDAT", &zlib::compress(&filtered));
+    write_chunk(&mut output, b"IEND", &[]);
+    Ok(output)
+}
+
+fn read_chunk<'a>(bytes: &'a [u8], position: &mut usize) -> Result<([u8; 4], &'a [u8])> {
+    let truncated = || Error::Decode("PNG chunk is truncated".to_string());
+    let start = *position;
+    let length_bytes = bytes.get(start..start + 4).ok_or_else(truncated)?;
+    let length = u32::from_be_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+    let end = start + 8 + length;
+    let chunk = bytes.get(start + 4..end).ok_or_else(truncated)?;
+    let crc = bytes.get(end..end + 4).ok_or_else(truncated)?;
+    if u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) != crc32(chunk) {
+        return Err(Error::Decode(format!(
+            "CRC mismatch in PNG chunk {}",
+            String::from_utf8_lossy(&chunk[..4])
+        )));
+    }
+    *position = end + 4;
+    Ok(([chunk[0], chunk[1], chunk[2], chunk[3]], &chunk[4..]))
+}
+
+fn write_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    let crc = crc32(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+impl Header {
+    fn passes(&self) -> &'static [(usize, usize, usize, usize)] {
+        if self.interlaced {
+            &ADAM7_PASSES
+        } else {
+            &[(0, 0, 1, 1)]
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    // Size of the filtered scanlines (filter byte plus row) of every pass
+    fn filtered_length(&self) -> Result<usize> {
+        let too_large = || Error::Decode(format!("PNG dimensions {}x{} are too large", self.width, self.height));
+        self.passes().iter().try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+            let pass_width = (self.width + dx - 1 - x0) / dx;
+            let pass_height = (self.height + dy - 1 - y0) / dy;
+            if pass_width == 0 {
+                return Ok(total);
+            }
+            let row_bytes = pass_width.checked_mul(self.bits_per_pixel()).ok_or_else(too_large)?.div_ceil(8);
+            (row_bytes + 1)
+                .checked_mul(pass_height)
+                .and_then(|length| length.checked_add(total))
+                .ok_or_else(too_large)
+        })
+    }
+}
+
+fn read_header(data: &[u8]) -> Result<Header> {
+    if data.len() != 13 {
+        return Err(Error::Decode("IHDR chunk must be 13 bytes".to_string()));
+    }
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let bit_depth = data[8];
+    let color_type = ColorType::from_byte(data[9])
+        .filter(|color_type| color_type.allows_bit_depth(bit_depth))
+        .ok_or_else(|| {
+            Error::Decode(format!("Unsupported PNG color type {} at bit depth {}", data[9], bit_depth))
+        })?;
+    if width == 0 || height == 0 || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+        return Err(Error::Decode("Invalid PNG header".to_string()));
+    }
+    Ok(Header { width, height, bit_depth, color_type, interlaced: data[12] == 1 })
+}
+
+// Reverses the row filters of every pass and unpacks the samples into
+// row-major, channel-interleaved order
+fn unfilter_image(raw: &[u8], header: &Header) -> Result<Vec<u32>> {
+    let channels = header.color_type.channels();
+    let bits_per_pixel = header.bits_per_pixel();
+    // Filters work on whole bytes; sub-byte pixels use the previous byte
+    let filter_stride = bits_per_pixel.div_ceil(8);
+
+    let mut samples = vec![0u32; header.width * header.height * channels];
+    let mut position = 0;
+    for &(x0, y0, dx, dy) in header.passes() {
+        let pass_width = (header.width + dx - 1 - x0) / dx;
+        let pass_height = (header.height + dy - 1 - y0) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let row_bytes = (pass_width * bits_per_pixel).div_ceil(8);
+        let mut previous = vec![0u8; row_bytes];
+        for pass_y in 0..pass_height {
+            let filter = *raw
+                .get(position)
+                .ok_or_else(|| Error::Decode("PNG image data is truncated".to_string()))?;
+            let row = raw
+                .get(position + 1..position + 1 + row_bytes)
+                .ok_or_else(|| Error::Decode("PNG image data is truncated".to_string()))?;
+            position += 1 + row_bytes;
+            let row = unfilter_row(filter, row, &previous, filter_stride)?;
+
+            let y = y0 + pass_y * dy;
+            for pass_x in 0..pass_width {
+                let x = x0 + pass_x * dx;
+                for channel in 0..channels {
+                    let index = pass_x * channels + channel;
+                    samples[(y * header.width + x) * channels + channel] = read_sample(&row, index, header.bit_depth);
+                }
+            }
+            previous = row;
+        }
+    }
+    Ok(samples)
+}
+
+fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u32 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as u32,
+        8 => row[index] as u32,
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << bits) - 1) as u8) as u32
+        }
+    }
+}
+
+fn unfilter_row(filter: u8, row: &[u8], previous: &[u8], stride: usize) -> Result<Vec<u8>> {
+    let mut output = row.to_vec();
+    for i in 0..output.len() {
+        let left = if i >= stride { output[i - stride] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= stride { previous[i - stride] } else { 0 };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(Error::Decode(format!("Invalid PNG filter type {}", filter))),
+        };
+        output[i] = output[i].wrapping_add(predictor);
+    }
+    Ok(output)
+}
+
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], stride: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let left = if i >= stride { row[i - stride] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= stride { previous[i - stride] } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                _ => paeth(left, up, up_left),
+            };
+            row[i].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_samples() {
+        // 648x648 palette image and 512x512 RGBA from the console's samples
+        let palette = decode(include_bytes!("../../dct-console/samples/4.png")).unwrap();
+        assert_eq!((palette.width, palette.height, palette.maxval), (648, 648, 255));
+        assert_eq!(palette.planes.len(), 3);
+        assert_eq!([palette.planes[0][200][100], palette.planes[2][123][300]], [189.0, 12.0]);
+
+        let rgba = decode(include_bytes!("../../dct-console/samples/3.png")).unwrap();
+        assert_eq!((rgba.width, rgba.height, rgba.planes.len()), (512, 512, 4));
+        assert_eq!([rgba.planes[1][256][256], rgba.planes[0][100][511], rgba.planes[3][0][0]], [96.0, 111.0, 255.0]);
+
+        let interlaced = decode(include_bytes!("../tests/fixtures/interlaced_gray4.png")).unwrap();
+        assert_eq!((interlaced.width, interlaced.height, interlaced.maxval), (7, 5, 15));
+        for (y, row) in interlaced.planes[0].iter().enumerate() {
+            for (x, &value) in row.iter().enumerate() {
+                assert_eq!(value, ((x + 2 * y) % 16) as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let plane = |offset: usize, maxval: usize| -> Matrix {
+            (0..9)
+                .map(|y| (0..11).map(|x| ((x * 37 + y * 91 + offset) % (maxval + 1)) as f64).collect())
+                .collect()
+        };
+        for maxval in [255, 65535] {
+            for count in 1..=4 {
+                let image = PlanarImage {
+                    width: 11,
+                    height: 9,
+                    maxval: maxval as u32,
+                    planes: (0..count).map(|channel| plane(channel * 13, maxval)).collect(),
+                };
+                assert_eq!(decode(&encode(&image).unwrap()).unwrap(), image);
+            }
+        }
+
+        let mut corrupt = encode(&PlanarImage::from_matrix(&plane(0, 255))).unwrap();
+        corrupt[PNG_SIGNATURE.len() + 10] ^= 1;
+        assert!(decode(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_rejects_header_that_does_not_match_data() {
+        // A 2x2 RGBA image whose IHDR claims other dimensions
+        let image = encode(&PlanarImage::from_planes(vec![vec![vec![0.0; 2]; 2]; 4])).unwrap();
+        let with_size = |width: u32, height: u32| {
+            let mut png = image.clone();
+            let ihdr = PNG_SIGNATURE.len() + 4;
+            png[ihdr + 4..ihdr + 8].copy_from_slice(&width.to_be_bytes());
+            png[ihdr + 8..ihdr + 12].copy_from_slice(&height.to_be_bytes());
+            let crc = crc32(&png[ihdr..ihdr + 17]);
+            png[ihdr + 17..ihdr + 21].copy_from_slice(&crc.to_be_bytes());
+            png
+        };
+
+        let error = |png: Vec<u8>| match decode(&png) {
+            Err(Error::Decode(message)) => message,
+            other => panic!("Expected a decode error, got {:?}", other.map(|image| image.width)),
+        };
+        assert!(error(with_size(200_000, 200_000)).contains("expected 160000200000 for"));
+        assert!(error(with_size(1, 1)).contains("inflates past 5 bytes"));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::error::{Error, Result};
+use crate::planar_image::{PlanarImage, MAX_MAXVAL};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PnmFormat {
+    // P2 / P5
+    PlainPgm,
+    RawPgm,
+    // P3 / P6
+    PlainPpm,
+    RawPpm,
+    // P7, any depth from 1 to 4
+    Pam,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<PlanarImage> {
+    let mut reader = HeaderReader { bytes, position: 0 };
+    let magic = reader.token()?;
+    let (depth, binary, width, height, maxval) = match magic.as_str() {
+        "P2" | "P3" | "P5" | "P6" => {
+            let depth = if magic == "P2" || magic == "P5" { 1 } else { 3 };
+            let width = reader.number()?;
+            let height = reader.number()?;
+            let maxval = reader.number()?;
+            (depth, magic == "P5" || magic == "P6", width, height, maxval)
+        }
+        "P7" => {
+            let (depth, width, height, maxval) = read_pam_header(&mut reader)?;
+            (depth, true, width, height, maxval)
+        }
+        _ => return Err(Error::Decode(format!("Unsupported PNM format {:?}", magic))),
+    };
+    if width == 0 || height == 0 || !(1..=4).contains(&depth) || !(1..=MAX_MAXVAL).contains(&maxval) {
+        return Err(Error::Decode("Invalid PNM header".to_string()));
+    }
+
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(depth))
+        .ok_or_else(|| Error::Decode(format!("PNM dimensions {}x{} are too large", width, height)))?;
+    let samples: Vec<u32> = if binary {
+        // A single whitespace byte separates the header from the raster
+        let start = reader.position + 1;
+        let sample_bytes = if maxval < 256 { 1 } else { 2 };
+        let raster = count
+            .checked_mul(sample_bytes)
+            .and_then(|length| length.checked_add(start))
+            .and_then(|end| bytes.get(start..end))
+            .ok_or_else(|| Error::Decode("PNM raster is truncated".to_string()))?;
+        raster
+            .chunks(sample_bytes)
+            .map(|sample| sample.iter().fold(0, |value, &byte| (value << 8) | byte as u32))
+            .collect()
+    } else {
+        // Plain samples take at least a digit and a separator each
+        if count > (bytes.len() - reader.position) / 2 {
+            return Err(Error::Decode("PNM raster is truncated".to_string()));
+        }
+        (0..count).map(|_| reader.number()).collect::<Result<_>>()?
+    };
+    if samples.iter().any(|&sample| sample > maxval) {
+        return Err(Error::Decode("PNM sample exceeds maxval".to_string()));
+    }
+
+    let planes = (0..depth)
+        .map(|channel| {
+            samples
+                .chunks(width * depth)
+                .map(|row| row.iter().skip(channel).step_by(depth).map(|&sample| sample as f64).collect())
+                .collect()
+        })
+        .collect();
+    Ok(PlanarImage { width, height, maxval, planes })
+}
+
+pub fn encode(image: &PlanarImage, format: PnmFormat) -> Result<Vec<u8>> {
+    image.validate()?;
+    let depth = image.planes.len();
+    let (magic, binary) = match format {
+        PnmFormat::PlainPgm if depth == 1 => ("P2", false),
+        PnmFormat::RawPgm if depth == 1 => ("P5", true),
+        PnmFormat::PlainPpm if depth == 3 => ("P3", false),
+        PnmFormat::RawPpm if depth == 3 => ("P6", true),
+        PnmFormat::Pam => ("P7", true),
+        _ => {
+            return Err(Error::InvalidImage(format!(
+                "{:?} cannot store {} planes",
+                format, depth
+            )))
+        }
+    };
+
+    let mut output = if format == PnmFormat::Pam {
+        let tuple_type = ["GRAYSCALE", "GRAYSCALE_ALPHA", "RGB", "RGB_ALPHA"][depth - 1];
+        format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+            image.width, image.height, depth, image.maxval, tuple_type
+        )
+    } else {
+        format!("{}\n{} {}\n{}\n", magic, image.width, image.height, image.maxval)
+    }
+    .into_bytes();
+
+    let maxval = image.maxval as f64;
+    let samples = (0..image.height).flat_map(|y| {
+        (0..image.width).flat_map(move |x| {
+            image.planes.iter().map(move |plane| plane[y][x].round().clamp(0.0, maxval) as u32)
+        })
+    });
+
+    if binary {
+        for sample in samples {
+            if image.maxval < 256 {
+                output.push(sample as u8);
+            } else {
+                output.extend_from_slice(&(sample as u16).to_be_bytes());
+            }
+        }
+    } else {
+        // One image row per line
+        let row_length = image.width * depth;
+        for (index, sample) in samples.enumerate() {
+            output.extend_from_slice(sample.to_string().as_bytes());
+            output.push(if (index + 1) % row_length == 0 { b'\n' } else { b' ' });
+        }
+    }
+    Ok(output)
+}
+
+fn read_pam_header(reader: &mut HeaderReader) -> Result<(usize, usize, usize, u32)> {
+    let (mut depth, mut width, mut height, mut maxval) = (None, None, None, None);
+    loop {
+        match reader.token()?.as_str() {
+            "WIDTH" => width = Some(reader.number()?),
+            "HEIGHT" => height = Some(reader.number()?),
+            "DEPTH" => depth = Some(reader.number()?),
+            "MAXVAL" => maxval = Some(reader.number()?),
+            // The tuple type is implied by the depth
+            "TUPLTYPE" => {
+                reader.token()?;
+            }
+            "ENDHDR" => break,
+            other => return Err(Error::Decode(format!("Unknown PAM header field {:?}", other))),
+        }
+    }
+    match (depth, width, height, maxval) {
+        (Some(depth), Some(width), Some(height), Some(maxval)) => Ok((depth, width, height, maxval)),
+        _ => Err(Error::Decode("PAM header is incomplete".to_string())),
+    }
+}
+
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl HeaderReader<'_> {
+    // Next whitespace-separated token, skipping `#` comments
+    fn token(&mut self) -> Result<String> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(b'#') => {
+                    while !matches!(self.bytes.get(self.position), None | Some(b'\n')) {
+                        self.position += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(Error::Decode("Unexpected end of PNM data".to_string())),
+            }
+        }
+        let start = self.position;
+        while matches!(self.bytes.get(self.position), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T> {
+        let token = self.token()?;
+        token
+            .parse()
+            .map_err(|_| Error::Decode(format!("Expected a number in PNM data, got {:?}", token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_fixtures() {
+        let gray = decode(include_bytes!("../tests/fixtures/gradient_16bit.pgm")).unwrap();
+        assert_eq!((gray.width, gray.height, gray.maxval), (4, 3, 65535));
+        assert_eq!(gray.planes[0][2], vec![0.0, 21845.0, 43690.0, 65535.0]);
+        assert_eq!(gray.to_luma()[2][3], 255.0);
+
+        let color = decode(include_bytes!("../tests/fixtures/primaries.ppm")).unwrap();
+        assert_eq!(color.planes.len(), 3);
+        assert_eq!(color.planes[0][0], vec![255.0, 0.0]);
+        assert_eq!(color.planes[2][1], vec![0.0, 255.0]);
+
+        let rgba = decode(include_bytes!("../tests/fixtures/checker_rgba.pam")).unwrap();
+        assert_eq!(rgba.planes.len(), 4);
+        assert_eq!(rgba.planes[3][0], vec![255.0, 0.0]);
+
+        match decode(b"P5 4294967296 4294967296 255\n\0") {
+            Err(Error::Decode(message)) => assert!(message.contains("too large"), "{}", message),
+            other => panic!("Expected a decode error, got {:?}", other.map(|image| image.width)),
+        }
+        assert!(decode(b"P2 1000000 1000000 255\n1 2 3").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        let gray = decode(include_bytes!("../tests/fixtures/gradient_16bit.pgm")).unwrap();
+        let color = decode(include_bytes!("../tests/fixtures/primaries.ppm")).unwrap();
+        let rgba = decode(include_bytes!("../tests/fixtures/checker_rgba.pam")).unwrap();
+
+        for (image, format) in [
+            (&gray, PnmFormat::PlainPgm),
+            (&gray, PnmFormat::RawPgm),
+            (&gray, PnmFormat::Pam),
+            (&color, PnmFormat::PlainPpm),
+            (&color, PnmFormat::RawPpm),
+            (&rgba, PnmFormat::Pam),
+        ] {
+            assert_eq!(&decode(&encode(image, format).unwrap()).unwrap(), image, "{:?}", format);
+        }
+        assert!(encode(&color, PnmFormat::RawPgm).is_err());
+    }
+}
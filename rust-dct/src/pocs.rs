@@ -0,0 +1,186 @@
+use crate::dct_compression::{calculate_dct_coefficients, PIXEL_NORMALIZATION_OFFSET};
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use crate::quality_metrics::QualityMetrics;
+use crate::quantizer::Quantizer;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_POCS_SMOOTHING: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PocsSmoothing {
+    pub iterations: usize,
+    // Blend between the current estimate (0) and its 3x3 box blur (1)
+    pub smoothing: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PocsResult {
+    pub iterations: usize,
+    pub smoothing: f64,
+    pub reconstructed_image: Matrix,
+    pub quality_metrics: QualityMetrics,
+}
+
+// Projection onto convex sets: alternately smooth the image and pull every
+// block's DCT back inside the interval its quantized level came from, so the
+// result stays consistent with the bits the decoder actually received
+pub fn pocs_reconstruct(
+    quantized_dct_matrices: &[Matrix],
+    quantization_tables: &[Matrix],
+    quantizer: Quantizer,
+    width: usize,
+    height: usize,
+    params: PocsSmoothing,
+) -> Result<Matrix, MatrixError> {
+    if params.smoothing.is_nan() || !(0.0..=1.0).contains(&params.smoothing) {
+        return Err(MatrixError::InvalidParameter(
+            "POCS smoothing must be between 0 and 1".to_string(),
+        ));
+    }
+
+    // Nothing to constrain without whole blocks; the image stays as compressed,
+    // which is all zeros
+    let block_size = match quantized_dct_matrices.first() {
+        Some(block) => block.len(),
+        None => return Ok(vec![vec![0.0; width]; height]),
+    };
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+    let covered_width = width / block_size * block_size;
+    let covered_height = height / block_size * block_size;
+
+    // Quantization cell [lower, upper] of every coefficient in every block
+    let intervals: Vec<(Matrix, Matrix)> = quantized_dct_matrices
+        .iter()
+        .zip(quantization_tables.iter())
+        .map(|(block, table)| quantization_interval(block, table, quantizer))
+        .collect();
+
+    let mut image = vec![vec![0.0; width]; height];
+    for (block_index, quantized_dct) in quantized_dct_matrices.iter().enumerate() {
+        let block = inverse_dct(quantized_dct, &dct_coefficient_matrix, &dct_coefficient_matrix_transposed)?;
+        matrix_ops::merge_blocks(&mut image, &block, block_index, block_size)?;
+    }
+
+    for _ in 0..params.iterations {
+        smooth(&mut image, covered_height, covered_width, params.smoothing);
+
+        let blocks = matrix_ops::partition_into_blocks(&image, block_size)?;
+        for (block_index, (block, (lower, upper))) in blocks.iter().zip(intervals.iter()).enumerate() {
+            let normalized: Matrix = block
+                .iter()
+                .map(|row| row.iter().map(|&value| value - PIXEL_NORMALIZATION_OFFSET).collect())
+                .collect();
+            let dct = matrix_ops::multiply_chain(&[
+                &dct_coefficient_matrix,
+                &normalized,
+                &dct_coefficient_matrix_transposed,
+            ])?;
+            let projected = project_onto_interval(&dct, lower, upper);
+            let block = inverse_dct(&projected, &dct_coefficient_matrix, &dct_coefficient_matrix_transposed)?;
+            matrix_ops::merge_blocks(&mut image, &block, block_index, block_size)?;
+        }
+
+        for value in image.iter_mut().flat_map(|row| row.iter_mut()) {
+            *value = value.clamp(0.0, 255.0);
+        }
+    }
+
+    Ok(image
+        .iter()
+        .map(|row| row.iter().map(|value| value.round()).collect())
+        .collect())
+}
+
+// Lower and upper bounds of the quantization cell behind every coefficient
+pub fn quantization_interval(
+    quantized_dct: &Matrix,
+    quantization_table: &Matrix,
+    quantizer: Quantizer,
+) -> (Matrix, Matrix) {
+    let cells: Vec<Vec<(f64, f64)>> = quantized_dct
+        .iter()
+        .zip(quantization_table.iter())
+        .map(|(row, steps)| {
+            row.iter()
+                .zip(steps.iter())
+                .map(|(&value, &step)| quantizer.cell((value / step).round(), step))
+                .collect()
+        })
+        .collect();
+
+    let lower = cells.iter().map(|row| row.iter().map(|cell| cell.0).collect()).collect();
+    let upper = cells.iter().map(|row| row.iter().map(|cell| cell.1).collect()).collect();
+    (lower, upper)
+}
+
+fn project_onto_interval(dct: &Matrix, lower: &Matrix, upper: &Matrix) -> Matrix {
+    dct.iter()
+        .zip(lower.iter().zip(upper.iter()))
+        .map(|(row, (lower_row, upper_row))| {
+            row.iter()
+                .zip(lower_row.iter().zip(upper_row.iter()))
+                .map(|(&value, (&low, &high))| value.clamp(low, high))
+                .collect()
+        })
+        .collect()
+}
+
+fn inverse_dct(dct: &Matrix, coefficients: &Matrix, coefficients_transposed: &Matrix) -> Result<Matrix, MatrixError> {
+    let block = matrix_ops::multiply_chain(&[coefficients_transposed, dct, coefficients])?;
+    Ok(block
+        .iter()
+        .map(|row| row.iter().map(|&value| value + PIXEL_NORMALIZATION_OFFSET).collect())
+        .collect())
+}
+
+// 3x3 box blur of the covered area (edges replicated), blended into the image
+fn smooth(image: &mut Matrix, rows: usize, cols: usize, amount: f64) {
+    let source = image.clone();
+    for (y, row) in image.iter_mut().enumerate().take(rows) {
+        for (x, value) in row.iter_mut().enumerate().take(cols) {
+            let mut sum = 0.0;
+            for dy in [-1isize, 0, 1] {
+                for dx in [-1isize, 0, 1] {
+                    let sy = (y as isize + dy).clamp(0, rows as isize - 1) as usize;
+                    let sx = (x as isize + dx).clamp(0, cols as isize - 1) as usize;
+                    sum += source[sy][sx];
+                }
+            }
+            *value = (1.0 - amount) * source[y][x] + amount * sum / 9.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_stays_inside_quantization_cells() {
+        let table = vec![vec![16.0; 8]; 8];
+        let mut left = vec![vec![0.0; 8]; 8];
+        let mut right = vec![vec![0.0; 8]; 8];
+        left[0][0] = -160.0;
+        right[0][0] = 160.0;
+        right[0][1] = 32.0;
+        let blocks = vec![left, right];
+
+        let params = PocsSmoothing { iterations: 5, smoothing: 1.0 };
+        let tables = vec![table.clone(), table];
+        let image = pocs_reconstruct(&blocks, &tables, Quantizer::Nearest, 16, 8, params).unwrap();
+
+        let coefficients = calculate_dct_coefficients(8).unwrap();
+        let transposed = matrix_ops::transpose(&coefficients).unwrap();
+        for (block, quantized) in matrix_ops::partition_into_blocks(&image, 8).unwrap().iter().zip(blocks.iter()) {
+            let normalized: Matrix = block.iter().map(|row| row.iter().map(|v| v - 127.0).collect()).collect();
+            let dct = matrix_ops::multiply_chain(&[&coefficients, &normalized, &transposed]).unwrap();
+            // Pixel rounding may push a coefficient slightly over the cell boundary
+            for (dct_row, quantized_row) in dct.iter().zip(quantized.iter()) {
+                for (&value, &level) in dct_row.iter().zip(quantized_row.iter()) {
+                    assert!((value - level).abs() <= 8.0 + 4.0);
+                }
+            }
+        }
+    }
+}
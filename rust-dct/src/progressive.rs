@@ -0,0 +1,201 @@
+use crate::dct_compression::{calculate_dct_coefficients, reconstruct_image_block};
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use serde::ser::{Error as _, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoefficientOrder {
+    Zigzag,
+    Magnitude,
+}
+
+// Order in which the coefficients of `dct_matrix` are switched on. Magnitude
+// order is stable, so equal coefficients keep their zigzag ranking
+pub fn coefficient_order(dct_matrix: &Matrix, order: CoefficientOrder) -> Vec<(usize, usize)> {
+    let mut indices = matrix_ops::zigzag_indices(dct_matrix.len());
+
+    if order == CoefficientOrder::Magnitude {
+        indices.sort_by(|&(a_row, a_col), &(b_row, b_col)| {
+            dct_matrix[b_row][b_col]
+                .abs()
+                .total_cmp(&dct_matrix[a_row][a_col].abs())
+        });
+    }
+
+    indices
+}
+
+// Frame k is the block rebuilt from its first k + 1 coefficients
+pub fn progressive_block_frames(
+    dct_matrix: &Matrix,
+    order: CoefficientOrder,
+) -> Result<Vec<Matrix>, MatrixError> {
+    let block_size = matrix_ops::square_block_size(std::slice::from_ref(dct_matrix))?;
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+
+    let mut partial_dct = vec![vec![0.0; block_size]; block_size];
+    coefficient_order(dct_matrix, order)
+        .into_iter()
+        .map(|(row, col)| {
+            partial_dct[row][col] = dct_matrix[row][col];
+            reconstruct_image_block(
+                &partial_dct,
+                &dct_coefficient_matrix_transposed,
+                &dct_coefficient_matrix,
+            )
+        })
+        .collect()
+}
+
+// Frame k is the whole image with every block rebuilt from its first k + 1
+// coefficients. Frames are built on request (or one at a time while
+// serializing), so at most one full-size frame is held at once
+pub struct ProgressiveImageFrames<'a> {
+    dct_matrices: &'a [Matrix],
+    orders: Vec<Vec<(usize, usize)>>,
+    width: usize,
+    height: usize,
+    block_size: usize,
+    dct_coefficient_matrix: Matrix,
+    dct_coefficient_matrix_transposed: Matrix,
+}
+
+// Checks that the blocks tile the whole-block area of a `width` x `height` image
+pub fn progressive_image_frames(
+    dct_matrices: &[Matrix],
+    width: usize,
+    height: usize,
+    order: CoefficientOrder,
+) -> Result<ProgressiveImageFrames<'_>, MatrixError> {
+    let block_size = matrix_ops::square_block_size(dct_matrices)?;
+    let block_count = (width / block_size) * (height / block_size);
+    if dct_matrices.len() != block_count {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "{} blocks of {}x{} do not tile a {}x{} image, which has {}",
+            dct_matrices.len(),
+            block_size,
+            block_size,
+            width,
+            height,
+            block_count
+        )));
+    }
+
+    let dct_coefficient_matrix = calculate_dct_coefficients(block_size)?;
+    let dct_coefficient_matrix_transposed = matrix_ops::transpose(&dct_coefficient_matrix)?;
+    Ok(ProgressiveImageFrames {
+        dct_matrices,
+        orders: dct_matrices.iter().map(|dct_matrix| coefficient_order(dct_matrix, order)).collect(),
+        width,
+        height,
+        block_size,
+        dct_coefficient_matrix,
+        dct_coefficient_matrix_transposed,
+    })
+}
+
+impl ProgressiveImageFrames<'_> {
+    pub fn frame_count(&self) -> usize {
+        self.block_size * self.block_size
+    }
+
+    // The image with every block rebuilt from its first `coefficient_count` coefficients
+    pub fn frame(&self, coefficient_count: usize) -> Result<Matrix, MatrixError> {
+        let mut frame = vec![vec![0.0; self.width]; self.height];
+        for (block_index, (dct_matrix, order)) in self.dct_matrices.iter().zip(&self.orders).enumerate() {
+            let mut partial_dct = vec![vec![0.0; self.block_size]; self.block_size];
+            for &(row, col) in order.iter().take(coefficient_count) {
+                partial_dct[row][col] = dct_matrix[row][col];
+            }
+            let block = reconstruct_image_block(
+                &partial_dct,
+                &self.dct_coefficient_matrix_transposed,
+                &self.dct_coefficient_matrix,
+            )?;
+            matrix_ops::merge_blocks(&mut frame, &block, block_index, self.block_size)?;
+        }
+        Ok(frame)
+    }
+}
+
+// A sequence of every frame, each dropped once written
+impl Serialize for ProgressiveImageFrames<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut frames = serializer.serialize_seq(Some(self.frame_count()))?;
+        for coefficient_count in 1..=self.frame_count() {
+            let frame = self.frame(coefficient_count).map_err(S::Error::custom)?;
+            frames.serialize_element(&frame)?;
+        }
+        frames.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_frame_matches_full_reconstruction() {
+        let mut dct_block = vec![vec![0.0; 8]; 8];
+        dct_block[0][0] = 160.0;
+        dct_block[0][1] = -40.0;
+        dct_block[3][2] = 25.0;
+
+        let frames = progressive_block_frames(&dct_block, CoefficientOrder::Zigzag).unwrap();
+        let coefficients = calculate_dct_coefficients(8).unwrap();
+        let full = reconstruct_image_block(
+            &dct_block,
+            &matrix_ops::transpose(&coefficients).unwrap(),
+            &coefficients,
+        )
+        .unwrap();
+
+        assert_eq!(frames.len(), 64);
+        assert!(frames[0].iter().flatten().all(|&value| value == 20.0 + 127.0));
+        assert_eq!(frames[63], full);
+
+        let mut ragged = dct_block;
+        ragged[5].pop();
+        assert!(matches!(
+            progressive_block_frames(&ragged, CoefficientOrder::Zigzag),
+            Err(MatrixError::IncompatibleDimensions(_))
+        ));
+        assert!(matches!(
+            progressive_block_frames(&Vec::new(), CoefficientOrder::Zigzag),
+            Err(MatrixError::EmptyMatrix)
+        ));
+    }
+
+    #[test]
+    fn test_image_frames_match_block_frames() {
+        let mut dct_matrices = vec![vec![vec![0.0; 8]; 8]; 2];
+        dct_matrices[0][0][0] = 160.0;
+        dct_matrices[1][2][1] = -30.0;
+
+        let frames = progressive_image_frames(&dct_matrices, 17, 9, CoefficientOrder::Magnitude).unwrap();
+        let block_frames = progressive_block_frames(&dct_matrices[1], CoefficientOrder::Magnitude).unwrap();
+        let frame = frames.frame(1).unwrap();
+        assert_eq!((frame.len(), frame[0].len()), (9, 17));
+        assert_eq!(frame[0][8..16], block_frames[0][0][..]);
+        assert_eq!(frame[8][0], 0.0);
+        let serialized: Vec<Matrix> = serde_json::from_value(serde_json::to_value(&frames).unwrap()).unwrap();
+        assert_eq!(serialized.len(), 64);
+        assert_eq!(serialized[63], frames.frame(64).unwrap());
+
+        assert!(progressive_image_frames(&dct_matrices, 8, 8, CoefficientOrder::Zigzag).is_err());
+        assert!(progressive_image_frames(&dct_matrices, 0, 0, CoefficientOrder::Zigzag).is_err());
+        assert!(progressive_image_frames(&[vec![vec![0.0; 8]; 4]], 8, 8, CoefficientOrder::Zigzag).is_err());
+    }
+
+    #[test]
+    fn test_magnitude_order_starts_with_largest_coefficient() {
+        let mut dct_block = vec![vec![0.0; 8]; 8];
+        dct_block[0][0] = 10.0;
+        dct_block[5][6] = -90.0;
+
+        let order = coefficient_order(&dct_block, CoefficientOrder::Magnitude);
+        assert_eq!(&order[..3], &[(5, 6), (0, 0), (0, 1)]);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::matrix_ops::{Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+
+const PEAK_VALUE: f64 = 255.0;
+const SSIM_WINDOW: usize = 7;
+const SSIM_K1: f64 = 0.01;
+const SSIM_K2: f64 = 0.03;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct QualityMetrics {
+    pub mse: f64,
+    // Infinite when the two images are identical
+    pub psnr: f64,
+    pub ssim: f64,
+}
+
+pub fn measure_quality(original: &Matrix, reconstructed: &Matrix) -> Result<QualityMetrics, MatrixError> {
+    let mse = mean_squared_error(original, reconstructed)?;
+    Ok(QualityMetrics {
+        mse,
+        psnr: psnr_from_mse(mse),
+        ssim: structural_similarity(original, reconstructed)?,
+    })
+}
+
+pub fn mean_squared_error(original: &Matrix, reconstructed: &Matrix) -> Result<f64, MatrixError> {
+    let (height, width) = check_same_dimensions(original, reconstructed)?;
+    let squared_error: f64 = original
+        .iter()
+        .flatten()
+        .zip(reconstructed.iter().flatten())
+        .map(|(&a, &b)| (a - b) * (a - b))
+        .sum();
+    Ok(squared_error / (width * height) as f64)
+}
+
+pub fn psnr_from_mse(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (PEAK_VALUE * PEAK_VALUE / mse).log10()
+    }
+}
+
+// Mean SSIM over 7x7 uniform windows (stride 1), as in scikit-image's default.
+// Images smaller than a window are compared as a single window
+pub fn structural_similarity(original: &Matrix, reconstructed: &Matrix) -> Result<f64, MatrixError> {
+    let (height, width) = check_same_dimensions(original, reconstructed)?;
+    let window_height = SSIM_WINDOW.min(height);
+    let window_width = SSIM_WINDOW.min(width);
+    let c1 = (SSIM_K1 * PEAK_VALUE).powi(2);
+    let c2 = (SSIM_K2 * PEAK_VALUE).powi(2);
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in 0..=height - window_height {
+        for left in 0..=width - window_width {
+            let samples = (window_height * window_width) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..top + window_height {
+                for x in left..left + window_width {
+                    let a = original[y][x];
+                    let b = reconstructed[y][x];
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+
+            let mean_a = sum_a / samples;
+            let mean_b = sum_b / samples;
+            let variance_a = sum_aa / samples - mean_a * mean_a;
+            let variance_b = sum_bb / samples - mean_b * mean_b;
+            let covariance = sum_ab / samples - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (variance_a + variance_b + c2));
+            windows += 1;
+        }
+    }
+
+    Ok(total / windows as f64)
+}
+
+fn check_same_dimensions(original: &Matrix, reconstructed: &Matrix) -> Result<(usize, usize), MatrixError> {
+    let height = original.len();
+    let width = original.first().map(|row| row.len()).unwrap_or(0);
+    if width == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if reconstructed.len() != height
+        || original.iter().chain(reconstructed.iter()).any(|row| row.len() != width)
+    {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Both images must be {}x{} to be compared",
+            width, height
+        )));
+    }
+    Ok((height, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_images() {
+        let image: Matrix = (0..16).map(|y| (0..16).map(|x| (x * y) as f64).collect()).collect();
+        let metrics = measure_quality(&image, &image).unwrap();
+        assert_eq!(metrics.mse, 0.0);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_psnr_of_constant_error() {
+        let original = vec![vec![100.0; 8]; 8];
+        let shifted = vec![vec![110.0; 8]; 8];
+        let metrics = measure_quality(&original, &shifted).unwrap();
+        assert_eq!(metrics.mse, 100.0);
+        assert!((metrics.psnr - 28.1308).abs() < 1e-4);
+        assert!(metrics.ssim < 1.0);
+    }
+}
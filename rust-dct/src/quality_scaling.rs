@@ -0,0 +1,79 @@
+use crate::matrix_ops::{Matrix, MatrixError};
+
+pub const DEFAULT_QUALITY: u32 = 50;
+const MAX_STEP: f64 = 255.0;
+
+// libjpeg's quality curve: percentage applied to the base table, 100 at quality 50
+pub fn ijg_scale_factor(quality: u32) -> Result<f64, MatrixError> {
+    match quality {
+        1..=49 => Ok(5000.0 / quality as f64),
+        50..=100 => Ok(200.0 - 2.0 * quality as f64),
+        _ => Err(MatrixError::InvalidParameter("Quality must be between 1 and 100".to_string())),
+    }
+}
+
+// Scales every step as libjpeg's jpeg_add_quant_table does (with force_baseline)
+pub fn scale_table(table: &Matrix, quality: u32) -> Result<Matrix, MatrixError> {
+    let scale = ijg_scale_factor(quality)?;
+    Ok(table
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&step| ((step * scale + 50.0) / 100.0).floor().clamp(1.0, MAX_STEP))
+                .collect()
+        })
+        .collect())
+}
+
+// Resamples a table to `size`x`size` so that each entry keeps the step of the
+// same spatial frequency (bilinear between the neighbouring source entries)
+pub fn resample_table(table: &Matrix, size: usize) -> Result<Matrix, MatrixError> {
+    let source_size = table.len();
+    if source_size == 0 || size == 0 {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if source_size == size {
+        return Ok(table.clone());
+    }
+
+    // Basis u of an N-block has the frequency of basis u·M/N of an M-block
+    let position = |index: usize| -> (usize, usize, f64) {
+        let exact = (index * source_size) as f64 / size as f64;
+        let lower = (exact.floor() as usize).min(source_size - 1);
+        let upper = (lower + 1).min(source_size - 1);
+        (lower, upper, exact - lower as f64)
+    };
+
+    Ok((0..size)
+        .map(|i| {
+            let (top, bottom, dy) = position(i);
+            (0..size)
+                .map(|j| {
+                    let (left, right, dx) = position(j);
+                    let upper_row = table[top][left] * (1.0 - dx) + table[top][right] * dx;
+                    let lower_row = table[bottom][left] * (1.0 - dx) + table[bottom][right] * dx;
+                    upper_row * (1.0 - dy) + lower_row * dy
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dct_compression::quantization_table;
+
+    #[test]
+    fn test_quality_scaling_matches_libjpeg() {
+        let base = quantization_table();
+        assert_eq!(scale_table(&base, 50).unwrap(), base);
+        assert_eq!(scale_table(&base, 75).unwrap()[0][..4], [8.0, 6.0, 5.0, 8.0]);
+        assert_eq!(scale_table(&base, 100).unwrap()[7][7], 1.0);
+        assert!(scale_table(&base, 0).is_err());
+
+        let resampled = resample_table(&base, 16).unwrap();
+        assert_eq!(resampled[0][0], base[0][0]);
+        assert_eq!(resampled[8][8], base[4][4]);
+    }
+}
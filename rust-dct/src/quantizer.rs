@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+// Rule that maps a DCT coefficient to an integer level of the quantization step.
+// Every rule except `Floor`, which rounds towards minus infinity, is symmetric
+// around zero.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Quantizer {
+    // round(x / q), the baseline JPEG rule
+    #[default]
+    Nearest,
+    // Zero bin `width` steps wide, uniform bins elsewhere; width 1 is `Nearest`
+    DeadZone { width: f64 },
+    // floor(|x| / q + offset), as in x264's deadzone; offset 0.5 is `Nearest`
+    RoundingOffset { offset: f64 },
+    // Towards zero
+    Truncate,
+    // Towards minus infinity
+    Floor,
+    // Trellis search per block minimizing distortion + lambda · bits; each
+    // level is either the rounded one or one step closer to zero
+    RateDistortion { lambda: f64 },
+}
+
+impl Quantizer {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Quantizer::DeadZone { width } if !(width.is_finite() && width >= 0.0) => {
+                Err("Dead-zone width must be a non-negative number".to_string())
+            }
+            // An offset of 1 would round zero itself up to level 1
+            Quantizer::RoundingOffset { offset } if !(0.0..1.0).contains(&offset) => {
+                Err("Rounding offset must be at least 0 and less than 1".to_string())
+            }
+            Quantizer::RateDistortion { lambda } if !(lambda.is_finite() && lambda >= 0.0) => {
+                Err("Rate-distortion lambda must be a non-negative number".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Per-coefficient level; `RateDistortion` decides per block and falls back to rounding here
+    pub fn level(&self, coefficient: f64, step: f64) -> f64 {
+        let ratio = coefficient / step;
+        let magnitude = ratio.abs();
+        let level = match *self {
+            Quantizer::Nearest | Quantizer::RateDistortion { .. } => ratio.round(),
+            Quantizer::DeadZone { width } => {
+                if magnitude < width / 2.0 {
+                    0.0
+                } else {
+                    ratio.signum() * magnitude.round().max(1.0)
+                }
+            }
+            Quantizer::RoundingOffset { offset } => ratio.signum() * (magnitude + offset).floor(),
+            Quantizer::Truncate => ratio.trunc(),
+            Quantizer::Floor => ratio.floor(),
+        };
+        // Normalize -0 so zero levels print and compare as plain zeros
+        if level == 0.0 {
+            0.0
+        } else {
+            level
+        }
+    }
+
+    // Range of coefficients [lower, upper] that this rule maps to `level`,
+    // used by decoders that need to stay consistent with the bitstream
+    pub fn cell(&self, level: f64, step: f64) -> (f64, f64) {
+        let magnitude = level.abs();
+        let (lower, upper) = match *self {
+            Quantizer::Nearest => (magnitude - 0.5, magnitude + 0.5),
+            Quantizer::DeadZone { width } if magnitude == 0.0 => (-width / 2.0, width / 2.0),
+            // Everything past the dead zone is at least level 1, also below 0.5 steps when width < 1
+            Quantizer::DeadZone { width } if magnitude == 1.0 => (width / 2.0, 1.5),
+            Quantizer::DeadZone { width } => ((magnitude - 0.5).max(width / 2.0), magnitude + 0.5),
+            Quantizer::RoundingOffset { offset } if magnitude == 0.0 => (offset - 1.0, 1.0 - offset),
+            Quantizer::RoundingOffset { offset } => (magnitude - offset, magnitude + 1.0 - offset),
+            Quantizer::Truncate if magnitude == 0.0 => (-1.0, 1.0),
+            Quantizer::Truncate => (magnitude, magnitude + 1.0),
+            Quantizer::Floor => return (level * step, (level + 1.0) * step),
+            // The trellis may zero a coefficient of any size, so a zero says nothing about it
+            Quantizer::RateDistortion { .. } if magnitude == 0.0 => (f64::NEG_INFINITY, f64::INFINITY),
+            Quantizer::RateDistortion { .. } => (magnitude - 0.5, magnitude + 1.5),
+        };
+
+        if level < 0.0 {
+            (-upper * step, -lower * step)
+        } else {
+            (lower * step, upper * step)
+        }
+    }
+
+    // The rules compared side by side in `CompressionResult::quantizer_comparison`
+    pub fn comparison_set() -> Vec<Quantizer> {
+        vec![
+            Quantizer::Nearest,
+            Quantizer::DeadZone { width: 1.5 },
+            Quantizer::RoundingOffset { offset: 1.0 / 3.0 },
+            Quantizer::RoundingOffset { offset: 1.0 / 6.0 },
+            Quantizer::Truncate,
+            Quantizer::Floor,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantizer_levels() {
+        assert_eq!(Quantizer::Nearest.level(-25.0, 10.0), -3.0);
+        assert_eq!(Quantizer::DeadZone { width: 1.5 }.level(7.0, 10.0), 0.0);
+        assert_eq!(Quantizer::DeadZone { width: 1.5 }.level(-8.0, 10.0), -1.0);
+        assert_eq!(Quantizer::RoundingOffset { offset: 1.0 / 3.0 }.level(16.0, 10.0), 1.0);
+        assert_eq!(Quantizer::RoundingOffset { offset: 1.0 / 3.0 }.level(17.0, 10.0), 2.0);
+        assert_eq!(Quantizer::Truncate.level(-19.0, 10.0), -1.0);
+        assert_eq!(Quantizer::Floor.level(-11.0, 10.0), -2.0);
+        assert!(Quantizer::Truncate.level(-9.0, 10.0).is_sign_positive());
+        assert_eq!(Quantizer::RateDistortion { lambda: 10.0 }.cell(-2.0, 10.0), (-35.0, -15.0));
+        assert_eq!(Quantizer::RateDistortion { lambda: 10.0 }.cell(0.0, 10.0), (f64::NEG_INFINITY, f64::INFINITY));
+        assert!(Quantizer::RoundingOffset { offset: 0.0 }.validate().is_ok());
+        assert!(Quantizer::RoundingOffset { offset: 1.0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_cells_contain_their_inputs() {
+        for quantizer in Quantizer::comparison_set().into_iter().chain([Quantizer::DeadZone { width: 0.5 }]) {
+            for coefficient in [-37.0, -12.5, -3.0, 0.0, 4.0, 9.9, 41.0] {
+                let level = quantizer.level(coefficient, 10.0);
+                let (lower, upper) = quantizer.cell(level, 10.0);
+                assert!(lower <= coefficient && coefficient <= upper, "{:?} {}", quantizer, coefficient);
+            }
+        }
+    }
+}
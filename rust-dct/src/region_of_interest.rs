@@ -0,0 +1,149 @@
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use crate::quality_metrics;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_ROI_FOREGROUND_SCALE: f64 = 1.0;
+pub const DEFAULT_ROI_BACKGROUND_SCALE: f64 = 2.0;
+// Blocks at or above this importance count as foreground in the report
+const FOREGROUND_THRESHOLD: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegionOfInterest {
+    // Importance in [0, 1], either one value per pixel (same size as the
+    // image) or one value per block (blocks_y × blocks_x)
+    pub mask: Matrix,
+    // Quantization scale of blocks with importance 1 and 0 respectively
+    pub foreground_scale: f64,
+    pub background_scale: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RegionOfInterestReport {
+    pub block_importance: Vec<f64>,
+    pub foreground_blocks: usize,
+    pub background_blocks: usize,
+    // None when the region has no blocks
+    pub foreground_psnr: Option<f64>,
+    pub background_psnr: Option<f64>,
+}
+
+impl RegionOfInterest {
+    // Mean importance of every block, in `partition_into_blocks` order
+    pub fn block_importance(&self, width: usize, height: usize, block_size: usize) -> Result<Vec<f64>, MatrixError> {
+        if self.mask.iter().flatten().any(|value| !(0.0..=1.0).contains(value)) {
+            return Err(MatrixError::InvalidParameter(
+                "Region-of-interest importance must be between 0 and 1".to_string(),
+            ));
+        }
+
+        let blocks_x = width / block_size;
+        let blocks_y = height / block_size;
+        let rows = self.mask.len();
+        let cols = self.mask.first().map(|row| row.len()).unwrap_or(0);
+        if self.mask.iter().any(|row| row.len() != cols) {
+            return Err(MatrixError::IncompatibleDimensions(
+                "Region-of-interest mask rows must all have the same length".to_string(),
+            ));
+        }
+
+        if rows == height && cols == width {
+            Ok(matrix_ops::partition_into_blocks(&self.mask, block_size)?
+                .iter()
+                .map(|block| block.iter().flatten().sum::<f64>() / (block_size * block_size) as f64)
+                .collect())
+        } else if rows == blocks_y && cols == blocks_x {
+            Ok(self.mask.iter().flatten().copied().collect())
+        } else {
+            Err(MatrixError::IncompatibleDimensions(format!(
+                "Region-of-interest mask is {}x{}, expected {}x{} pixels or {}x{} blocks",
+                rows, cols, height, width, blocks_y, blocks_x
+            )))
+        }
+    }
+
+    // Geometric interpolation between the background and foreground scales
+    pub fn quantization_scales(&self, block_importance: &[f64]) -> Result<Vec<f64>, MatrixError> {
+        let valid = |scale: f64| scale.is_finite() && scale > 0.0;
+        if !valid(self.foreground_scale) || !valid(self.background_scale) {
+            return Err(MatrixError::InvalidParameter(
+                "Region-of-interest scales must be positive numbers".to_string(),
+            ));
+        }
+
+        Ok(block_importance
+            .iter()
+            .map(|&importance| {
+                self.foreground_scale.powf(importance) * self.background_scale.powf(1.0 - importance)
+            })
+            .collect())
+    }
+}
+
+// PSNR of the foreground and background blocks separately
+pub fn region_report(
+    block_importance: Vec<f64>,
+    original_blocks: &[Matrix],
+    compressed_blocks: &[Matrix],
+) -> Result<RegionOfInterestReport, MatrixError> {
+    let mut squared_errors = [0.0; 2];
+    let mut samples = [0usize; 2];
+    let mut block_counts = [0usize; 2];
+
+    for ((&importance, original), compressed) in block_importance
+        .iter()
+        .zip(original_blocks.iter())
+        .zip(compressed_blocks.iter())
+    {
+        let region = if importance >= FOREGROUND_THRESHOLD { 0 } else { 1 };
+        let mse = quality_metrics::mean_squared_error(original, compressed)?;
+        let block_samples = original.iter().map(|row| row.len()).sum::<usize>();
+        squared_errors[region] += mse * block_samples as f64;
+        samples[region] += block_samples;
+        block_counts[region] += 1;
+    }
+
+    let psnr = |region: usize| {
+        (samples[region] > 0).then(|| quality_metrics::psnr_from_mse(squared_errors[region] / samples[region] as f64))
+    };
+
+    Ok(RegionOfInterestReport {
+        foreground_blocks: block_counts[0],
+        background_blocks: block_counts[1],
+        foreground_psnr: psnr(0),
+        background_psnr: psnr(1),
+        block_importance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_scales(mask: Matrix) -> RegionOfInterest {
+        RegionOfInterest {
+            mask,
+            foreground_scale: DEFAULT_ROI_FOREGROUND_SCALE,
+            background_scale: DEFAULT_ROI_BACKGROUND_SCALE,
+        }
+    }
+
+    #[test]
+    fn test_pixel_and_block_masks_agree() {
+        let mut pixel_mask = vec![vec![0.0; 16]; 8];
+        for row in pixel_mask.iter_mut() {
+            for value in row.iter_mut().take(8) {
+                *value = 1.0;
+            }
+        }
+        let per_pixel = default_scales(pixel_mask);
+        let per_block = default_scales(vec![vec![1.0, 0.0]]);
+
+        let importance = per_pixel.block_importance(16, 8, 8).unwrap();
+        assert_eq!(importance, per_block.block_importance(16, 8, 8).unwrap());
+        assert_eq!(
+            per_pixel.quantization_scales(&importance).unwrap(),
+            vec![DEFAULT_ROI_FOREGROUND_SCALE, DEFAULT_ROI_BACKGROUND_SCALE]
+        );
+        assert!(default_scales(vec![vec![1.0]]).block_importance(16, 8, 8).is_err());
+    }
+}
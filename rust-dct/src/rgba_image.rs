@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+// Row-major RGBA8 pixel buffer, laid out like canvas `ImageData`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        let offset = (y * self.width + x) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&rgba);
+    }
+}
+
+pub fn gray_to_rgba(value: f64) -> [u8; 4] {
+    let level = value.round().clamp(0.0, 255.0) as u8;
+    [level, level, level, 255]
+}
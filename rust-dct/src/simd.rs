@@ -0,0 +1,306 @@
+// SIMD kernels for the fixed 8x8 block path: matrix products for the forward
+// and inverse DCT, nearest-level quantization and BT.601 luma conversion.
+// x86_64 picks AVX at runtime, wasm32 uses simd128 when compiled with
+// `-C target-feature=+simd128` (see `.cargo/config.toml`), everything else
+// runs the scalar loops. Every kernel does the same operations in the same
+// order as its scalar version, so results are bit-identical on all backends.
+use crate::matrix_ops::Matrix;
+
+pub const BLOCK_SIZE: usize = 8;
+
+// Row-major 8x8 block
+pub type Block = [f64; BLOCK_SIZE * BLOCK_SIZE];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Avx,
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    Simd128,
+}
+
+// Backend the kernels dispatch to on this machine
+pub fn backend() -> Backend {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return Backend::Avx;
+        }
+    }
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        return Backend::Simd128;
+    }
+    #[allow(unreachable_code)]
+    Backend::Scalar
+}
+
+// `None` unless the matrix is exactly 8x8
+pub fn block_from_matrix(matrix: &Matrix) -> Option<Block> {
+    if matrix.len() != BLOCK_SIZE || matrix.iter().any(|row| row.len() != BLOCK_SIZE) {
+        return None;
+    }
+    let mut block = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+    for (chunk, row) in block.chunks_exact_mut(BLOCK_SIZE).zip(matrix) {
+        chunk.copy_from_slice(row);
+    }
+    Some(block)
+}
+
+pub fn block_to_matrix(block: &Block) -> Matrix {
+    block.chunks_exact(BLOCK_SIZE).map(|row| row.to_vec()).collect()
+}
+
+// left · right, summing over k in order like `matrix_ops::multiply`
+pub fn multiply(left: &Block, right: &Block) -> Block {
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: AVX support was just detected
+        Backend::Avx => unsafe { avx::multiply(left, right) },
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        Backend::Simd128 => simd128::multiply(left, right),
+        _ => scalar::multiply(left, right),
+    }
+}
+
+// left · block · right, i.e. C·X·Cᵀ for the forward DCT and Cᵀ·Y·C for the
+// inverse one
+pub fn transform(left: &Block, block: &Block, right: &Block) -> Block {
+    multiply(&multiply(left, block), right)
+}
+
+// Dequantized coefficients for `Quantizer::Nearest`: round(c / q) · q, with
+// halves rounded away from zero and -0 levels normalized to 0
+pub fn quantize_nearest(coefficients: &Block, table: &Block) -> Block {
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: AVX support was just detected
+        Backend::Avx => unsafe { avx::quantize_nearest(coefficients, table) },
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        Backend::Simd128 => simd128::quantize_nearest(coefficients, table),
+        _ => scalar::quantize_nearest(coefficients, table),
+    }
+}
+
+// 0.299 R + 0.587 G + 0.114 B for each pixel of a row; all slices must have
+// the length of `luma`
+pub fn luma_row(red: &[f64], green: &[f64], blue: &[f64], luma: &mut [f64]) {
+    assert!(red.len() == luma.len() && green.len() == luma.len() && blue.len() == luma.len());
+    match backend() {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: AVX support was just detected and the lengths were checked
+        Backend::Avx => unsafe { avx::luma_row(red, green, blue, luma) },
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        Backend::Simd128 => simd128::luma_row(red, green, blue, luma),
+        _ => scalar::luma_row(red, green, blue, luma),
+    }
+}
+
+const LUMA_WEIGHTS: [f64; 3] = [0.299, 0.587, 0.114];
+
+mod scalar {
+    use super::{Block, BLOCK_SIZE, LUMA_WEIGHTS};
+
+    pub fn multiply(left: &Block, right: &Block) -> Block {
+        let mut result = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        for i in 0..BLOCK_SIZE {
+            for j in 0..BLOCK_SIZE {
+                for k in 0..BLOCK_SIZE {
+                    result[i * BLOCK_SIZE + j] += left[i * BLOCK_SIZE + k] * right[k * BLOCK_SIZE + j];
+                }
+            }
+        }
+        result
+    }
+
+    pub fn quantize_nearest(coefficients: &Block, table: &Block) -> Block {
+        let mut quantized = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        for ((value, &coefficient), &step) in quantized.iter_mut().zip(coefficients).zip(table) {
+            let level = (coefficient / step).round();
+            *value = if level == 0.0 { 0.0 } else { level } * step;
+        }
+        quantized
+    }
+
+    pub fn luma_row(red: &[f64], green: &[f64], blue: &[f64], luma: &mut [f64]) {
+        for (x, value) in luma.iter_mut().enumerate() {
+            *value = LUMA_WEIGHTS[0] * red[x] + LUMA_WEIGHTS[1] * green[x] + LUMA_WEIGHTS[2] * blue[x];
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx {
+    use super::{Block, BLOCK_SIZE, LUMA_WEIGHTS};
+    use std::arch::x86_64::*;
+
+    // Four columns of a result row at a time; no FMA, so every product is
+    // rounded before the add exactly like the scalar loop
+    #[target_feature(enable = "avx")]
+    pub unsafe fn multiply(left: &Block, right: &Block) -> Block {
+        let mut result = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        for i in 0..BLOCK_SIZE {
+            for j in (0..BLOCK_SIZE).step_by(4) {
+                let mut sum = _mm256_setzero_pd();
+                for k in 0..BLOCK_SIZE {
+                    let a = _mm256_set1_pd(left[i * BLOCK_SIZE + k]);
+                    let b = _mm256_loadu_pd(right.as_ptr().add(k * BLOCK_SIZE + j));
+                    sum = _mm256_add_pd(sum, _mm256_mul_pd(a, b));
+                }
+                _mm256_storeu_pd(result.as_mut_ptr().add(i * BLOCK_SIZE + j), sum);
+            }
+        }
+        result
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn quantize_nearest(coefficients: &Block, table: &Block) -> Block {
+        let mut quantized = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        let half = _mm256_set1_pd(0.5);
+        let one = _mm256_set1_pd(1.0);
+        let sign_mask = _mm256_set1_pd(-0.0);
+        for offset in (0..quantized.len()).step_by(4) {
+            let step = _mm256_loadu_pd(table.as_ptr().add(offset));
+            let ratio = _mm256_div_pd(_mm256_loadu_pd(coefficients.as_ptr().add(offset)), step);
+            // round() as trunc plus a signed one where the dropped fraction is at least a half
+            let truncated = _mm256_round_pd(ratio, _MM_FROUND_TO_ZERO | _MM_FROUND_NO_EXC);
+            let fraction = _mm256_andnot_pd(sign_mask, _mm256_sub_pd(ratio, truncated));
+            let carry = _mm256_and_pd(_mm256_cmp_pd(fraction, half, _CMP_GE_OQ), one);
+            let level = _mm256_add_pd(truncated, _mm256_or_pd(carry, _mm256_and_pd(ratio, sign_mask)));
+            // -0 + 0 is +0, every other level is unchanged
+            let level = _mm256_add_pd(level, _mm256_setzero_pd());
+            _mm256_storeu_pd(quantized.as_mut_ptr().add(offset), _mm256_mul_pd(level, step));
+        }
+        quantized
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn luma_row(red: &[f64], green: &[f64], blue: &[f64], luma: &mut [f64]) {
+        let [wr, wg, wb] = LUMA_WEIGHTS.map(|weight| _mm256_set1_pd(weight));
+        let vector_end = luma.len() / 4 * 4;
+        for x in (0..vector_end).step_by(4) {
+            let r = _mm256_mul_pd(wr, _mm256_loadu_pd(red.as_ptr().add(x)));
+            let g = _mm256_mul_pd(wg, _mm256_loadu_pd(green.as_ptr().add(x)));
+            let b = _mm256_mul_pd(wb, _mm256_loadu_pd(blue.as_ptr().add(x)));
+            _mm256_storeu_pd(luma.as_mut_ptr().add(x), _mm256_add_pd(_mm256_add_pd(r, g), b));
+        }
+        super::scalar::luma_row(&red[vector_end..], &green[vector_end..], &blue[vector_end..], &mut luma[vector_end..]);
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod simd128 {
+    use super::{Block, BLOCK_SIZE, LUMA_WEIGHTS};
+    use core::arch::wasm32::*;
+
+    fn load(values: &[f64], offset: usize) -> v128 {
+        f64x2(values[offset], values[offset + 1])
+    }
+
+    fn store(values: &mut [f64], offset: usize, vector: v128) {
+        values[offset] = f64x2_extract_lane::<0>(vector);
+        values[offset + 1] = f64x2_extract_lane::<1>(vector);
+    }
+
+    // Two columns of a result row at a time, same operation order as the scalar loop
+    pub fn multiply(left: &Block, right: &Block) -> Block {
+        let mut result = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        for i in 0..BLOCK_SIZE {
+            for j in (0..BLOCK_SIZE).step_by(2) {
+                let mut sum = f64x2_splat(0.0);
+                for k in 0..BLOCK_SIZE {
+                    let a = f64x2_splat(left[i * BLOCK_SIZE + k]);
+                    sum = f64x2_add(sum, f64x2_mul(a, load(right, k * BLOCK_SIZE + j)));
+                }
+                store(&mut result, i * BLOCK_SIZE + j, sum);
+            }
+        }
+        result
+    }
+
+    pub fn quantize_nearest(coefficients: &Block, table: &Block) -> Block {
+        let mut quantized = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        let half = f64x2_splat(0.5);
+        let one = f64x2_splat(1.0);
+        let sign_mask = f64x2_splat(-0.0);
+        for offset in (0..quantized.len()).step_by(2) {
+            let step = load(table, offset);
+            let ratio = f64x2_div(load(coefficients, offset), step);
+            let truncated = f64x2_trunc(ratio);
+            let fraction = f64x2_abs(f64x2_sub(ratio, truncated));
+            let carry = v128_and(f64x2_ge(fraction, half), one);
+            let level = f64x2_add(truncated, v128_or(carry, v128_and(ratio, sign_mask)));
+            let level = f64x2_add(level, f64x2_splat(0.0));
+            store(&mut quantized, offset, f64x2_mul(level, step));
+        }
+        quantized
+    }
+
+    pub fn luma_row(red: &[f64], green: &[f64], blue: &[f64], luma: &mut [f64]) {
+        let [wr, wg, wb] = LUMA_WEIGHTS.map(|weight| f64x2_splat(weight));
+        let vector_end = luma.len() / 2 * 2;
+        for x in (0..vector_end).step_by(2) {
+            let r = f64x2_mul(wr, load(red, x));
+            let g = f64x2_mul(wg, load(green, x));
+            let b = f64x2_mul(wb, load(blue, x));
+            store(luma, x, f64x2_add(f64x2_add(r, g), b));
+        }
+        super::scalar::luma_row(&red[vector_end..], &green[vector_end..], &blue[vector_end..], &mut luma[vector_end..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dct_compression::calculate_dct_coefficients;
+    use crate::matrix_ops;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn test_block(seed: usize) -> Block {
+        let mut block = [0.0; BLOCK_SIZE * BLOCK_SIZE];
+        for (index, value) in block.iter_mut().enumerate() {
+            *value = ((index * 37 + seed * 101) % 255) as f64 - 128.0 + (index % 7) as f64 * 0.125;
+        }
+        block
+    }
+
+    // Runs under wasm-bindgen-test on wasm32, where the simd128 kernels are used
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_kernels_match_scalar_and_matrix_ops() {
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        assert_eq!(backend(), Backend::Simd128);
+        let dct = calculate_dct_coefficients(BLOCK_SIZE).unwrap();
+        let dct_transposed = matrix_ops::transpose(&dct).unwrap();
+        let (left, right) = (block_from_matrix(&dct).unwrap(), block_from_matrix(&dct_transposed).unwrap());
+        let table = block_from_matrix(&crate::dct_compression::quantization_table()).unwrap();
+
+        for seed in 0..16 {
+            let block = test_block(seed);
+            let coefficients = transform(&left, &block, &right);
+            let expected = matrix_ops::multiply_chain(&[&dct, &block_to_matrix(&block), &dct_transposed]).unwrap();
+            assert_eq!(block_to_matrix(&coefficients), expected);
+            assert_eq!(coefficients, scalar::multiply(&scalar::multiply(&left, &block), &right));
+
+            // Coefficients exactly halfway between levels, of both signs
+            let mut halves = coefficients;
+            halves[1] = 2.5 * table[1];
+            halves[2] = -2.5 * table[2];
+            halves[3] = -0.25 * table[3];
+            assert_eq!(quantize_nearest(&halves, &table), scalar::quantize_nearest(&halves, &table));
+            let quantized = quantize_nearest(&halves, &table);
+            assert_eq!((quantized[1], quantized[2]), (3.0 * table[1], -3.0 * table[2]));
+            assert_eq!(quantized[3].to_bits(), 0.0f64.to_bits());
+        }
+
+        let red: Vec<f64> = (0..37).map(|x| (x * 7 % 256) as f64).collect();
+        let green: Vec<f64> = (0..37).map(|x| (x * 13 % 256) as f64).collect();
+        let blue: Vec<f64> = (0..37).map(|x| (x * 29 % 256) as f64).collect();
+        let (mut luma, mut expected) = (vec![0.0; 37], vec![0.0; 37]);
+        luma_row(&red, &green, &blue, &mut luma);
+        scalar::luma_row(&red, &green, &blue, &mut expected);
+        assert_eq!(luma, expected);
+    }
+}
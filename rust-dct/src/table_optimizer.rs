@@ -0,0 +1,228 @@
+use crate::dct_compression::{self, quantization_table};
+use crate::matrix_ops::{Matrix, MatrixError};
+use crate::quality_metrics;
+use crate::quantizer::Quantizer;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MAX_PASSES: usize = 40;
+// Each candidate move multiplies one step by this factor (and adds at least 1)
+const STEP_GROWTH: f64 = 1.25;
+const MAX_STEP: f64 = 255.0;
+// Reported for lossless reconstructions instead of an infinite PSNR, which
+// JSON cannot represent
+const MAX_PSNR: f64 = 100.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum QualityTarget {
+    Psnr { value: f64 },
+    Ssim { value: f64 },
+}
+
+impl QualityTarget {
+    fn value(&self) -> f64 {
+        match *self {
+            QualityTarget::Psnr { value } | QualityTarget::Ssim { value } => value,
+        }
+    }
+
+    fn measure(&self, original: &Matrix, reconstructed: &Matrix) -> Result<f64, MatrixError> {
+        match self {
+            QualityTarget::Psnr { .. } => Ok(quality_metrics::psnr_from_mse(quality_metrics::mean_squared_error(
+                original,
+                reconstructed,
+            )?)
+            .min(MAX_PSNR)),
+            QualityTarget::Ssim { .. } => quality_metrics::structural_similarity(original, reconstructed),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TableOptimization {
+    // Every image must reach this quality
+    pub target: QualityTarget,
+    #[serde(default = "default_max_passes")]
+    pub max_passes: usize,
+}
+
+fn default_max_passes() -> usize {
+    DEFAULT_MAX_PASSES
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OptimizedTable {
+    pub table: Matrix,
+    pub target: QualityTarget,
+    // Achieved PSNR or SSIM of every image, in input order
+    pub image_quality: Vec<f64>,
+    pub total_bits: usize,
+    pub passes: usize,
+}
+
+impl OptimizedTable {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+struct TrainingImage {
+    dct_matrices: Vec<Matrix>,
+    covered_original: Matrix,
+}
+
+struct Evaluation {
+    total_bits: usize,
+    image_quality: Vec<f64>,
+}
+
+impl Evaluation {
+    fn meets(&self, target: QualityTarget) -> bool {
+        self.image_quality.iter().all(|&quality| quality >= target.value())
+    }
+}
+
+// Greedy coordinate search for the 8x8 table with the fewest coded bits that
+// still reaches the quality target on every image. Starting from unit steps,
+// each pass tries to grow every step, ranks the moves by bits saved per unit
+// of quality lost, and applies them in that order as long as the target holds.
+pub fn optimize_quantization_table(
+    images: &[Matrix],
+    params: TableOptimization,
+) -> Result<OptimizedTable, MatrixError> {
+    if images.is_empty() {
+        return Err(MatrixError::EmptyMatrix);
+    }
+    if !params.target.value().is_finite() {
+        return Err(MatrixError::InvalidParameter("Quality target must be a finite number".to_string()));
+    }
+
+    let block_size = quantization_table().len();
+    let training = images
+        .iter()
+        .map(|image| {
+            let (dct_matrices, covered_original) = dct_compression::block_dct(image, block_size)?;
+            Ok(TrainingImage { dct_matrices, covered_original })
+        })
+        .collect::<Result<Vec<_>, MatrixError>>()?;
+
+    let mut table = vec![vec![1.0; block_size]; block_size];
+    let mut current = evaluate(&training, &table, params.target)?;
+    if !current.meets(params.target) {
+        return Err(MatrixError::InvalidParameter(
+            "Quality target is not reachable even with unit quantization steps".to_string(),
+        ));
+    }
+
+    let mut passes = 0;
+    while passes < params.max_passes {
+        passes += 1;
+
+        let mut moves = Vec::new();
+        for i in 0..block_size {
+            for j in 0..block_size {
+                let step = table[i][j];
+                if step >= MAX_STEP {
+                    continue;
+                }
+                let grown = (step * STEP_GROWTH).round().max(step + 1.0).min(MAX_STEP);
+                let trial = evaluate(&training, &with_step(&table, i, j, grown), params.target)?;
+                if trial.total_bits >= current.total_bits || !trial.meets(params.target) {
+                    continue;
+                }
+                let bits_saved = (current.total_bits - trial.total_bits) as f64;
+                let quality_lost: f64 = current
+                    .image_quality
+                    .iter()
+                    .zip(trial.image_quality.iter())
+                    .map(|(before, after)| before - after)
+                    .sum();
+                moves.push((bits_saved / quality_lost.max(f64::EPSILON), i, j, grown));
+            }
+        }
+        moves.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // Moves were ranked against the table at the start of the pass, so
+        // each one is re-checked against the table as it now stands
+        let mut accepted = false;
+        for (_, i, j, grown) in moves {
+            let candidate = with_step(&table, i, j, grown);
+            let trial = evaluate(&training, &candidate, params.target)?;
+            if trial.meets(params.target) && trial.total_bits < current.total_bits {
+                table = candidate;
+                current = trial;
+                accepted = true;
+            }
+        }
+        if !accepted {
+            break;
+        }
+    }
+
+    Ok(OptimizedTable {
+        table,
+        target: params.target,
+        image_quality: current.image_quality,
+        total_bits: current.total_bits,
+        passes,
+    })
+}
+
+fn with_step(table: &Matrix, row: usize, col: usize, step: f64) -> Matrix {
+    let mut table = table.clone();
+    table[row][col] = step;
+    table
+}
+
+fn evaluate(training: &[TrainingImage], table: &Matrix, target: QualityTarget) -> Result<Evaluation, MatrixError> {
+    let mut total_bits = 0;
+    let mut image_quality = Vec::with_capacity(training.len());
+    for image in training {
+        let encoded = dct_compression::encode_and_reconstruct(
+            &image.dct_matrices,
+            &vec![table.clone(); image.dct_matrices.len()],
+            &image.covered_original,
+            Quantizer::Nearest,
+        )?;
+        total_bits += encoded.entropy_coded_size.total_bits;
+        image_quality.push(target.measure(&image.covered_original, &encoded.reconstructed_image)?);
+    }
+    Ok(Evaluation { total_bits, image_quality })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops;
+
+    #[test]
+    fn test_optimized_table_meets_target_with_fewer_bits() {
+        let image = matrix_ops::test_image(16, 16);
+        let target = QualityTarget::Psnr { value: 38.0 };
+
+        let unit_bits = evaluate(
+            &[TrainingImage {
+                dct_matrices: dct_compression::block_dct(&image, 8).unwrap().0,
+                covered_original: image.clone(),
+            }],
+            &vec![vec![1.0; 8]; 8],
+            target,
+        )
+        .unwrap()
+        .total_bits;
+        let optimized = optimize_quantization_table(&[image], TableOptimization { target, max_passes: 10 }).unwrap();
+
+        assert!(optimized.image_quality[0] >= 38.0);
+        assert!(optimized.total_bits < unit_bits);
+        assert!(optimized.table.iter().flatten().any(|&step| step > 1.0));
+        let parsed: OptimizedTable = serde_json::from_str(&optimized.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.table, optimized.table);
+
+        // Unit steps reconstruct a flat image exactly
+        let flat = vec![vec![100.0; 16]; 16];
+        let lossless = optimize_quantization_table(&[flat], TableOptimization { target, max_passes: 0 }).unwrap();
+        assert_eq!(lossless.image_quality, vec![MAX_PSNR]);
+        let parsed: OptimizedTable = serde_json::from_str(&lossless.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.image_quality, lossless.image_quality);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::coefficient_stats::quantized_levels;
+use crate::entropy_coding::{zigzag_levels, HuffmanCostModel};
+use crate::matrix_ops::{self, Matrix, MatrixError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrellisBlockDecision {
+    pub block_index: usize,
+    // Coefficients whose level differs from plain rounding
+    pub changed_coefficients: usize,
+    pub rounded_bits: usize,
+    pub trellis_bits: usize,
+    pub rounded_distortion: f64,
+    pub trellis_distortion: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TrellisReport {
+    pub lambda: f64,
+    pub blocks: Vec<TrellisBlockDecision>,
+    pub changed_coefficients: usize,
+    pub rounded_bits: usize,
+    pub trellis_bits: usize,
+    pub rounded_distortion: f64,
+    pub trellis_distortion: f64,
+}
+
+// Rate-distortion optimized quantization of one block, in the spirit of
+// mozjpeg's trellis: walking the AC coefficients in zigzag order, every
+// coefficient keeps its rounded level, drops one level towards zero, or joins
+// a zero run, whichever minimizes squared error + lambda · bits for the whole
+// block (including the EOB). The DC stays rounded, since its cost depends on
+// the neighbouring block through DPCM. Returns the dequantized block.
+pub fn trellis_quantize(
+    dct_matrix: &Matrix,
+    quantization_table: &Matrix,
+    lambda: f64,
+    cost_model: &HuffmanCostModel,
+) -> Result<Matrix, MatrixError> {
+    let block_size = quantization_table.len();
+    if dct_matrix.len() != block_size || dct_matrix.iter().any(|row| row.len() != block_size) {
+        return Err(MatrixError::IncompatibleDimensions(format!(
+            "Expected {}x{} matrix for quantization",
+            block_size, block_size
+        )));
+    }
+
+    let positions = matrix_ops::zigzag_indices(block_size);
+    let count = positions.len();
+    let coefficient = |z: usize| dct_matrix[positions[z].0][positions[z].1];
+    let step = |z: usize| quantization_table[positions[z].0][positions[z].1];
+
+    // zero_distortion_prefix[z] = distortion of zeroing coefficients 1..z
+    let mut zero_distortion_prefix = vec![0.0; count];
+    for z in 1..count {
+        zero_distortion_prefix[z] = zero_distortion_prefix[z - 1] + coefficient(z).powi(2);
+    }
+    let zeroed_between = |from: usize, to: usize| -> f64 {
+        // Distortion of zeroing the coefficients strictly between `from` and `to`
+        if to > from + 1 {
+            zero_distortion_prefix[to - 1] - zero_distortion_prefix[from]
+        } else {
+            0.0
+        }
+    };
+
+    // best_cost[z]: cheapest way to code 1..=z with z as the last nonzero level
+    // (z = 0 stands for "only the DC so far")
+    let mut best_cost = vec![f64::INFINITY; count];
+    let mut best_level = vec![0i64; count];
+    let mut previous_nonzero = vec![0usize; count];
+    best_cost[0] = 0.0;
+
+    for z in 1..count {
+        let rounded = (coefficient(z) / step(z)).round() as i64;
+        let candidates = [rounded, rounded - rounded.signum()];
+
+        for &level in candidates.iter().filter(|&&level| level != 0) {
+            let distortion = (coefficient(z) - level as f64 * step(z)).powi(2);
+            for j in 0..z {
+                if !best_cost[j].is_finite() {
+                    continue;
+                }
+                let cost = best_cost[j]
+                    + zeroed_between(j, z)
+                    + distortion
+                    + lambda * cost_model.ac_bits(z - j - 1, level) as f64;
+                if cost < best_cost[z] {
+                    best_cost[z] = cost;
+                    best_level[z] = level;
+                    previous_nonzero[z] = j;
+                }
+            }
+        }
+    }
+
+    let mut last_nonzero = 0;
+    let mut best_total = f64::INFINITY;
+    for (j, &cost) in best_cost.iter().enumerate() {
+        if !cost.is_finite() {
+            continue;
+        }
+        let end_of_block = if j < count - 1 {
+            lambda * cost_model.end_of_block_bits() as f64
+        } else {
+            0.0
+        };
+        let total = cost + zeroed_between(j, count) + end_of_block;
+        if total < best_total {
+            best_total = total;
+            last_nonzero = j;
+        }
+    }
+
+    let mut quantized = vec![vec![0.0; block_size]; block_size];
+    let (dc_row, dc_col) = positions[0];
+    quantized[dc_row][dc_col] = (coefficient(0) / step(0)).round() * step(0);
+
+    let mut z = last_nonzero;
+    while z > 0 {
+        let (row, col) = positions[z];
+        quantized[row][col] = best_level[z] as f64 * step(z);
+        z = previous_nonzero[z];
+    }
+
+    Ok(quantized)
+}
+
+// Compares the trellis levels of every block with plain rounding
+pub fn trellis_report(
+    dct_matrices: &[Matrix],
+    compressed_dct_matrices: &[Matrix],
+    quantization_tables: &[Matrix],
+    lambda: f64,
+    cost_model: &HuffmanCostModel,
+) -> TrellisReport {
+    let distortion = |original: &Matrix, quantized: &Matrix| -> f64 {
+        original
+            .iter()
+            .flatten()
+            .zip(quantized.iter().flatten())
+            .map(|(&a, &b)| (a - b).powi(2))
+            .sum()
+    };
+
+    let blocks: Vec<TrellisBlockDecision> = dct_matrices
+        .iter()
+        .zip(compressed_dct_matrices.iter().zip(quantization_tables.iter()))
+        .enumerate()
+        .map(|(block_index, (dct_matrix, (trellis_block, table)))| {
+            let rounded_levels = quantized_levels(dct_matrix, table);
+            let trellis_levels = quantized_levels(trellis_block, table);
+            let rounded_block: Matrix = rounded_levels
+                .iter()
+                .zip(table.iter())
+                .map(|(row, steps)| row.iter().zip(steps.iter()).map(|(&l, &q)| l as f64 * q).collect())
+                .collect();
+
+            TrellisBlockDecision {
+                block_index,
+                changed_coefficients: rounded_levels
+                    .iter()
+                    .flatten()
+                    .zip(trellis_levels.iter().flatten())
+                    .filter(|(a, b)| a != b)
+                    .count(),
+                rounded_bits: cost_model.block_ac_bits(&zigzag_levels(&rounded_levels)),
+                trellis_bits: cost_model.block_ac_bits(&zigzag_levels(&trellis_levels)),
+                rounded_distortion: distortion(dct_matrix, &rounded_block),
+                trellis_distortion: distortion(dct_matrix, trellis_block),
+            }
+        })
+        .collect();
+
+    TrellisReport {
+        lambda,
+        changed_coefficients: blocks.iter().map(|block| block.changed_coefficients).sum(),
+        rounded_bits: blocks.iter().map(|block| block.rounded_bits).sum(),
+        trellis_bits: blocks.iter().map(|block| block.trellis_bits).sum(),
+        rounded_distortion: blocks.iter().map(|block| block.rounded_distortion).sum(),
+        trellis_distortion: blocks.iter().map(|block| block.trellis_distortion).sum(),
+        blocks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_block() -> Matrix {
+        (0..8)
+            .map(|i| (0..8).map(|j| ((i * 8 + j) as f64 * 7.3).sin() * 60.0 / (1 + i + j) as f64).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_zero_lambda_matches_rounding() {
+        let table = vec![vec![8.0; 8]; 8];
+        let block = sample_block();
+
+        let quantized = trellis_quantize(&block, &table, 0.0, &HuffmanCostModel::standard()).unwrap();
+
+        for (row, quantized_row) in block.iter().zip(quantized.iter()) {
+            for (&value, &level) in row.iter().zip(quantized_row.iter()) {
+                assert_eq!(level, (value / 8.0).round() * 8.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_larger_lambda_spends_fewer_bits() {
+        let table = vec![vec![8.0; 8]; 8];
+        let block = sample_block();
+        let model = HuffmanCostModel::standard();
+
+        let trellis = trellis_quantize(&block, &table, 200.0, &model).unwrap();
+        let report = trellis_report(&[block], &[trellis], &[table], 200.0, &model);
+
+        assert!(report.changed_coefficients > 0);
+        assert!(report.trellis_bits < report.rounded_bits);
+        assert!(report.trellis_distortion >= report.rounded_distortion);
+    }
+}
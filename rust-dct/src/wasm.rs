@@ -0,0 +1,644 @@
+// JavaScript bindings used by the browser demo (the `wasm` feature)
+use wasm_bindgen::prelude::*;
+use serde_wasm_bindgen::{from_value, to_value};
+use serde::Serialize;
+use crate::adaptive_quantization::AdaptiveQuantization;
+use crate::block_export::BlockMatrixKind;
+use crate::coefficient_mask::CoefficientMask;
+use crate::dct_compression::{
+    CompressionConfig, CompressionJob, CompressionProgress, CompressionResult, QuantizationTableSource,
+};
+use crate::deblocking::DeblockingFilter;
+use crate::energy_compaction::DEFAULT_COMPACTION_COEFFICIENTS;
+use crate::encoder::{Compressed, Encoder};
+use crate::error_maps::Colormap;
+use crate::matrix_ops::{Matrix, MatrixError};
+use crate::perceptual_table::ViewingConditions;
+use crate::planar_image::PlanarImage;
+use crate::pocs::{PocsSmoothing, DEFAULT_POCS_SMOOTHING};
+use crate::progressive::CoefficientOrder;
+use crate::quantizer::Quantizer;
+use crate::region_of_interest::{RegionOfInterest, DEFAULT_ROI_BACKGROUND_SCALE, DEFAULT_ROI_FOREGROUND_SCALE};
+use crate::table_optimizer::TableOptimization;
+use crate::dctz::DctzImage;
+use crate::{coefficient_mask, dct_basis, dct_compression, dctz, perceptual_table, png, progressive, table_optimizer};
+
+#[derive(Debug)]
+pub enum WasmError {
+    Deserialization(String),
+    Serialization(String),
+    Compression(String),
+}
+
+// Implementation to convert our custom error into a JavaScript error
+impl From<WasmError> for JsValue {
+    fn from(error: WasmError) -> JsValue {
+        let error_message = match error {
+            WasmError::Deserialization(msg) => format!("Failed to parse input data: {}", msg),
+            WasmError::Serialization(msg) => format!("Failed to prepare output data: {}", msg),
+            WasmError::Compression(msg) => format!("Image compression failed: {}", msg),
+        };
+        JsValue::from_str(&error_message)
+    }
+}
+
+// Type alias for our Result type to simplify error handling
+type WasmResult<T> = Result<T, WasmError>;
+
+// Exported as `initThreadPool`; with the `parallel` feature the page must await
+// `initThreadPool(navigator.hardwareConcurrency)` once before compressing
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+#[wasm_bindgen(start)]
+pub fn initialize() {
+    // Set up panic hook for better error messages in the browser console
+    #[cfg(feature = "console_error_panic_hook")]
+    console_error_panic_hook::set_once();
+}
+
+// Renders the N×N grid of DCT basis patterns as an RGBA pixel buffer
+#[wasm_bindgen]
+pub fn generate_dct_basis_image(block_size: usize, upscale: usize) -> Result<JsValue, JsValue> {
+    let basis_image = dct_basis::generate_basis_image(block_size, upscale)
+        .map_err(|e| WasmError::Compression(e.to_string()))?;
+
+    to_value(&basis_image)
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+// Quantization table derived from the contrast sensitivity of the eye under
+// the given viewing conditions ({ viewing_distance, display_dpi, block_size, peak_step })
+#[wasm_bindgen]
+pub fn generate_perceptual_quantization_table(conditions: JsValue) -> Result<JsValue, JsValue> {
+    let conditions: ViewingConditions = from_value(conditions)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+    let table = perceptual_table::generate_perceptual_table(conditions)
+        .map_err(|e| WasmError::Compression(e.to_string()))?;
+
+    to_value(&table)
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+// Searches for the 8x8 table with the fewest bits at a quality target over a
+// set of images; `params` is e.g. { target: { metric: "psnr", value: 38 } }.
+// Returns the table and its measurements as JSON
+#[wasm_bindgen]
+pub fn optimize_quantization_table(images: JsValue, params: JsValue) -> Result<String, JsValue> {
+    let images: Vec<Matrix> = from_value(images)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+    let params: TableOptimization = from_value(params)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    let optimized = table_optimizer::optimize_quantization_table(&images, params)
+        .map_err(|e| WasmError::Compression(e.to_string()))?;
+
+    optimized
+        .to_json()
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+// 8-bit grayscale PNG file of a 0–255 matrix, e.g. a reconstruction to download
+#[wasm_bindgen]
+pub fn encode_png(image_data: JsValue) -> Result<Vec<u8>, JsValue> {
+    let image: Matrix = from_value(image_data)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    png::encode(&PlanarImage::from_matrix(&image))
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+// JSON document of every block's original, DCT, quantized and reconstructed
+// matrices, from a result returned by `compress_image`
+#[wasm_bindgen]
+pub fn export_blocks_json(compression_result: JsValue) -> Result<String, JsValue> {
+    let result: CompressionResult = from_value(compression_result)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    result
+        .blocks_to_json()
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+// CSV of one kind of block matrix ("original", "dct", "quantized" or "reconstructed")
+#[wasm_bindgen]
+pub fn export_blocks_csv(compression_result: JsValue, kind: JsValue) -> Result<String, JsValue> {
+    let result: CompressionResult = from_value(compression_result)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+    let kind: BlockMatrixKind = from_value(kind)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    Ok(result.blocks_to_csv(kind))
+}
+
+// `.npy` bytes of one kind of block matrix, shaped (blocks_y, blocks_x, N, N)
+#[wasm_bindgen]
+pub fn export_blocks_npy(compression_result: JsValue, kind: JsValue) -> Result<Vec<u8>, JsValue> {
+    let result: CompressionResult = from_value(compression_result)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+    let kind: BlockMatrixKind = from_value(kind)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    Ok(result.blocks_to_npy(kind))
+}
+
+// `.npy` bytes of the reconstructed image, shaped (height, width), from a
+// result returned by `compress_image_file`. Cropped and clamped to 0–255 like
+// the CLI's `.reconstructed.npy`
+#[wasm_bindgen]
+pub fn export_reconstruction_npy(compressed: JsValue) -> Result<Vec<u8>, JsValue> {
+    let compressed: Compressed = from_value(compressed)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+    Ok(compressed.reconstruction_to_npy())
+}
+
+#[derive(Serialize)]
+struct DecodedDctz {
+    #[serde(flatten)]
+    container: DctzImage,
+    reconstructed: Matrix,
+}
+
+// Reads a `.dctz` file saved by `compress_image_to_dctz`: its header, levels
+// and the reconstructed image
+#[wasm_bindgen]
+pub fn decode_dctz(file_bytes: &[u8]) -> Result<JsValue, JsValue> {
+    let container = dctz::decode(file_bytes)
+        .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+    let reconstructed = container
+        .reconstruct()
+        .map_err(|e| WasmError::Compression(e.to_string()))?;
+
+    to_value(&DecodedDctz { container, reconstructed })
+        .map_err(|e| JsValue::from(WasmError::Serialization(e.to_string())))
+}
+
+#[wasm_bindgen]
+pub struct CompressionOptions {
+    width: usize,
+    height: usize,
+    colormap: Colormap,
+    compaction_coefficients: usize,
+    deblocking_strength: Option<f64>,
+    pocs_iterations: Option<usize>,
+    pocs_smoothing: f64,
+    quantizer: Quantizer,
+    adaptive_quantization: Option<AdaptiveQuantization>,
+    roi_foreground_scale: f64,
+    roi_background_scale: f64,
+    quantization_table: QuantizationTableSource,
+    compare_quantizers: bool,
+    explain_blocks: bool,
+}
+
+#[wasm_bindgen]
+impl CompressionOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            colormap: Colormap::default(),
+            compaction_coefficients: DEFAULT_COMPACTION_COEFFICIENTS,
+            deblocking_strength: None,
+            pocs_iterations: None,
+            pocs_smoothing: DEFAULT_POCS_SMOOTHING,
+            quantizer: Quantizer::default(),
+            adaptive_quantization: None,
+            roi_foreground_scale: DEFAULT_ROI_FOREGROUND_SCALE,
+            roi_background_scale: DEFAULT_ROI_BACKGROUND_SCALE,
+            quantization_table: QuantizationTableSource::default(),
+            compare_quantizers: false,
+            // The demo shows each block's walkthrough on click
+            explain_blocks: true,
+        }
+    }
+
+    // Colormap used by the error heatmaps: "grayscale", "hot", "jet" or "viridis"
+    #[wasm_bindgen(setter)]
+    pub fn set_colormap(&mut self, colormap: JsValue) -> Result<(), JsValue> {
+        self.colormap = from_value(colormap)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        Ok(())
+    }
+
+    // K for the "energy in the first K zigzag coefficients" report
+    #[wasm_bindgen(setter)]
+    pub fn set_compaction_coefficients(&mut self, k: usize) {
+        self.compaction_coefficients = k;
+    }
+
+    // Enables the deblocking post-filter; `undefined` turns it off
+    #[wasm_bindgen(setter)]
+    pub fn set_deblocking_strength(&mut self, strength: Option<f64>) {
+        self.deblocking_strength = strength;
+    }
+
+    // Enables POCS dequantization smoothing with this many iterations
+    #[wasm_bindgen(setter)]
+    pub fn set_pocs_iterations(&mut self, iterations: Option<usize>) {
+        self.pocs_iterations = iterations;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_pocs_smoothing(&mut self, smoothing: f64) {
+        self.pocs_smoothing = smoothing;
+    }
+
+    // e.g. { mode: "dead_zone", width: 1.5 }, { mode: "rounding_offset", offset: 0.33 }
+    // or { mode: "rate_distortion", lambda: 50 }
+    #[wasm_bindgen(setter)]
+    pub fn set_quantizer(&mut self, quantizer: JsValue) -> Result<(), JsValue> {
+        self.quantizer = from_value(quantizer)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        Ok(())
+    }
+
+    // e.g. { metric: "variance", strength: 0.5 }; `null` turns adaptation off
+    #[wasm_bindgen(setter)]
+    pub fn set_adaptive_quantization(&mut self, params: JsValue) -> Result<(), JsValue> {
+        self.adaptive_quantization = from_value(params)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        Ok(())
+    }
+
+    // Quantization scale of fully important blocks in `compress_image_with_roi`
+    #[wasm_bindgen(setter)]
+    pub fn set_roi_foreground_scale(&mut self, scale: f64) {
+        self.roi_foreground_scale = scale;
+    }
+
+    // Quantization scale of blocks outside the region of interest
+    #[wasm_bindgen(setter)]
+    pub fn set_roi_background_scale(&mut self, scale: f64) {
+        self.roi_background_scale = scale;
+    }
+
+    // { source: "annex_k" }, { source: "custom", table: [[...]] } or { source: "perceptual",
+    // viewing_distance: 24, display_dpi: 96, block_size: 8 }, with an optional peak_step (default 10)
+    #[wasm_bindgen(setter)]
+    pub fn set_quantization_table(&mut self, source: JsValue) -> Result<(), JsValue> {
+        self.quantization_table = from_value(source)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        Ok(())
+    }
+
+    // Fills `quantizer_comparison` with every rule applied to the image
+    #[wasm_bindgen(setter)]
+    pub fn set_compare_quantizers(&mut self, compare: bool) {
+        self.compare_quantizers = compare;
+    }
+
+    // Fills `latex_calculations` with each block's MathML walkthrough (on by default)
+    #[wasm_bindgen(setter)]
+    pub fn set_explain_blocks(&mut self, explain: bool) {
+        self.explain_blocks = explain;
+    }
+
+    fn compression_config(&self) -> CompressionConfig {
+        CompressionConfig {
+            colormap: self.colormap,
+            compaction_coefficients: self.compaction_coefficients,
+            deblocking: self
+                .deblocking_strength
+                .map(|strength| DeblockingFilter { strength }),
+            pocs: self.pocs_iterations.map(|iterations| PocsSmoothing {
+                iterations,
+                smoothing: self.pocs_smoothing,
+            }),
+            quantizer: self.quantizer,
+            adaptive_quantization: self.adaptive_quantization,
+            region_of_interest: None,
+            quantization_table: self.quantization_table.clone(),
+            compare_quantizers: self.compare_quantizers,
+            explain_blocks: self.explain_blocks,
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct ImageProcessor {
+    options: CompressionOptions,
+    // Work in progress of the incremental `start`/`step`/`finish` API
+    job: Option<CompressionJob>,
+}
+
+#[wasm_bindgen]
+impl ImageProcessor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: CompressionOptions) -> Self {
+        Self { options, job: None }
+    }
+
+    // Main compression function that processes the image data
+    pub fn compress_image(&self, image_data: JsValue) -> Result<JsValue, JsValue> {
+        self.process_compression(image_data, None)
+            .map_err(Into::into)
+    }
+
+    // Like `compress_image`, with an importance mask in [0, 1] given per pixel
+    // or per 8x8 block: important blocks keep finer quantization steps
+    pub fn compress_image_with_roi(&self, image_data: JsValue, importance_mask: JsValue) -> Result<JsValue, JsValue> {
+        self.process_compression(image_data, Some(importance_mask))
+            .map_err(Into::into)
+    }
+
+    // Incremental `compress_image` for a Web Worker that keeps its message loop
+    // responsive: `start`, then `step` (a few blocks at a time) until the
+    // returned progress { processed_blocks, total_blocks, done } is done, then
+    // `finish` for the result. `cancel` drops the work in progress, and a new
+    // `start` replaces it.
+    pub fn start(&mut self, image_data: JsValue) -> Result<JsValue, JsValue> {
+        self.process_start(image_data, None)
+            .map_err(Into::into)
+    }
+
+    // `start` with the importance mask of `compress_image_with_roi`
+    pub fn start_with_roi(&mut self, image_data: JsValue, importance_mask: JsValue) -> Result<JsValue, JsValue> {
+        self.process_start(image_data, Some(importance_mask))
+            .map_err(Into::into)
+    }
+
+    pub fn step(&mut self, block_count: usize) -> Result<JsValue, JsValue> {
+        self.process_step(block_count)
+            .map_err(Into::into)
+    }
+
+    pub fn finish(&mut self) -> Result<JsValue, JsValue> {
+        self.process_finish()
+            .map_err(Into::into)
+    }
+
+    pub fn cancel(&mut self) {
+        self.job = None;
+    }
+
+    // Compresses the luma of a PNG/PGM/PPM/PAM file as read from disk, so the
+    // page no longer decodes it through a canvas. The image size comes from
+    // the file (not the options); edges are replicated to whole blocks and the
+    // result is an `Encoder` output.
+    pub fn compress_image_file(&self, file_bytes: &[u8]) -> Result<JsValue, JsValue> {
+        self.process_file_compression(file_bytes)
+            .map_err(Into::into)
+    }
+
+    // Compresses the image like `compress_image` and returns the `.dctz`
+    // container bytes, to save the compressed state between sessions
+    pub fn compress_image_to_dctz(&self, image_data: JsValue) -> Result<Vec<u8>, JsValue> {
+        self.process_dctz_compression(image_data)
+            .map_err(Into::into)
+    }
+
+    // Re-renders the blocks affected by a per-frequency mask (all blocks, or only `block_index`)
+    pub fn reconstruct_with_mask(
+        &self,
+        dct_matrices: JsValue,
+        mask: JsValue,
+        block_index: Option<usize>,
+    ) -> Result<JsValue, JsValue> {
+        self.process_masked_reconstruction(dct_matrices, mask, block_index)
+            .map_err(Into::into)
+    }
+
+    // Reconstructions from the first 1, 2, … N² coefficients, for one block or the whole image
+    pub fn progressive_frames(
+        &self,
+        dct_matrices: JsValue,
+        order: JsValue,
+        block_index: Option<usize>,
+    ) -> Result<JsValue, JsValue> {
+        self.process_progressive_frames(dct_matrices, order, block_index)
+            .map_err(Into::into)
+    }
+
+    // Internal helper function to handle the actual compression logic
+    fn process_compression(&self, image_data: JsValue, importance_mask: Option<JsValue>) -> WasmResult<JsValue> {
+        let (image_matrix, config) = self.prepare_compression(image_data, importance_mask)?;
+
+        // Perform the DCT compression
+        let compression_result = dct_compression::compress_image_dct(
+            image_matrix,
+            self.options.width,
+            self.options.height,
+            &config,
+        ).map_err(|e| WasmError::Compression(e.to_string()))?;
+
+        // Convert the result back to JavaScript
+        self.serialize_result(compression_result)
+    }
+
+    fn process_start(&mut self, image_data: JsValue, importance_mask: Option<JsValue>) -> WasmResult<JsValue> {
+        let (image_matrix, config) = self.prepare_compression(image_data, importance_mask)?;
+        let job = CompressionJob::new(image_matrix, self.options.width, self.options.height, config)
+            .map_err(|e| WasmError::Compression(e.to_string()))?;
+        let progress = job.progress();
+        self.job = Some(job);
+        self.serialize_progress(progress)
+    }
+
+    fn process_step(&mut self, block_count: usize) -> WasmResult<JsValue> {
+        let job = self.job.as_mut().ok_or_else(no_compression_in_progress)?;
+        let progress = job.step(block_count);
+        if progress.is_err() {
+            self.job = None;
+        }
+        self.serialize_progress(progress.map_err(|e| WasmError::Compression(e.to_string()))?)
+    }
+
+    fn process_finish(&mut self) -> WasmResult<JsValue> {
+        // Checked before taking the job, so finishing early keeps the blocks done so far
+        let progress = self.job.as_ref().ok_or_else(no_compression_in_progress)?.progress();
+        if !progress.done {
+            return Err(WasmError::Compression(format!(
+                "{} of {} blocks and {} of {} stages processed; call step until done",
+                progress.processed_blocks, progress.total_blocks, progress.completed_stages, progress.total_stages
+            )));
+        }
+        let job = self.job.take().ok_or_else(no_compression_in_progress)?;
+        let compression_result = job.finish()
+            .map_err(|e| WasmError::Compression(e.to_string()))?;
+        self.serialize_result(compression_result)
+    }
+
+    // Parses the image (and optional importance mask) into the pipeline's input
+    fn prepare_compression(
+        &self,
+        image_data: JsValue,
+        importance_mask: Option<JsValue>,
+    ) -> WasmResult<(Matrix, CompressionConfig)> {
+        // Convert JavaScript array into Rust Matrix type
+        let image_matrix: Matrix = from_value(image_data)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+        // Validate image dimensions
+        self.validate_dimensions(&image_matrix)?;
+
+        let mut config = self.options.compression_config();
+        if let Some(mask) = importance_mask {
+            let mask: Matrix = from_value(mask)
+                .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+            config.region_of_interest = Some(RegionOfInterest {
+                mask,
+                foreground_scale: self.options.roi_foreground_scale,
+                background_scale: self.options.roi_background_scale,
+            });
+        }
+        Ok((image_matrix, config))
+    }
+
+    fn process_file_compression(&self, file_bytes: &[u8]) -> WasmResult<JsValue> {
+        let image = PlanarImage::decode(file_bytes)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?
+            .to_luma();
+        let compressed = Encoder::from_config(self.options.compression_config())
+            .encode(&image)
+            .map_err(|e| WasmError::Compression(e.to_string()))?;
+        to_value(&compressed)
+            .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+
+    fn process_dctz_compression(&self, image_data: JsValue) -> WasmResult<Vec<u8>> {
+        let image_matrix: Matrix = from_value(image_data)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        self.validate_dimensions(&image_matrix)?;
+
+        let compressed = Encoder::from_config(self.options.compression_config())
+            .encode(&image_matrix)
+            .map_err(|e| WasmError::Compression(e.to_string()))?;
+        dctz::encode(&compressed)
+            .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+
+    fn process_masked_reconstruction(
+        &self,
+        dct_matrices: JsValue,
+        mask: JsValue,
+        block_index: Option<usize>,
+    ) -> WasmResult<JsValue> {
+        let dct_matrices: Vec<Matrix> = from_value(dct_matrices)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        let mask: CoefficientMask = from_value(mask)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+        let masked_blocks = coefficient_mask::reconstruct_masked_blocks(&dct_matrices, &mask, block_index)
+            .map_err(|e| WasmError::Compression(e.to_string()))?;
+
+        to_value(&masked_blocks)
+            .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+
+    fn process_progressive_frames(
+        &self,
+        dct_matrices: JsValue,
+        order: JsValue,
+        block_index: Option<usize>,
+    ) -> WasmResult<JsValue> {
+        let dct_matrices: Vec<Matrix> = from_value(dct_matrices)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+        let order: CoefficientOrder = from_value(order)
+            .map_err(|e| WasmError::Deserialization(e.to_string()))?;
+
+        match block_index {
+            Some(index) => {
+                let frames = dct_matrices
+                    .get(index)
+                    .ok_or(MatrixError::BlockIndexOutOfRange {
+                        index,
+                        block_count: dct_matrices.len(),
+                    })
+                    .and_then(|dct_matrix| progressive::progressive_block_frames(dct_matrix, order))
+                    .map_err(|e| WasmError::Compression(e.to_string()))?;
+                to_value(&frames)
+            }
+            None => {
+                // Serialized frame by frame rather than collected first
+                let frames = progressive::progressive_image_frames(
+                    &dct_matrices,
+                    self.options.width,
+                    self.options.height,
+                    order,
+                )
+                .map_err(|e| WasmError::Compression(e.to_string()))?;
+                to_value(&frames)
+            }
+        }
+        .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+
+    // Validation helper to ensure image dimensions are correct
+    fn validate_dimensions(&self, image: &Matrix) -> WasmResult<()> {
+        let actual_height = image.len();
+        let actual_width = image.first()
+            .map(|row| row.len())
+            .unwrap_or(0);
+
+        if actual_height != self.options.height || actual_width != self.options.width {
+            return Err(WasmError::Deserialization(format!(
+                "Image dimensions mismatch. Expected {}x{}, got {}x{}",
+                self.options.width, self.options.height,
+                actual_width, actual_height
+            )));
+        }
+        Ok(())
+    }
+
+    fn serialize_progress(&self, progress: CompressionProgress) -> WasmResult<JsValue> {
+        to_value(&progress)
+            .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+
+    // Serialization helper to convert Rust types to JavaScript
+    fn serialize_result(&self, result: CompressionResult) -> WasmResult<JsValue> {
+        to_value(&result)
+            .map_err(|e| WasmError::Serialization(e.to_string()))
+    }
+}
+
+fn no_compression_in_progress() -> WasmError {
+    WasmError::Compression("No compression in progress; call start first".to_string())
+}
+
+// JavaScript usage example (in comments for documentation)
+/*
+// JavaScript code:
+import init, { ImageProcessor, CompressionOptions } from './pkg/your_module';
+
+async function compressImage(imageData, width, height) {
+    await init();
+    
+    const options = new CompressionOptions(width, height);
+    const processor = new ImageProcessor(options);
+    
+    try {
+        const result = processor.compress_image(imageData);
+        console.log('Compression successful:', result);
+    } catch (error) {
+        console.error('Compression failed:', error);
+    }
+}
+
+// Inside a Web Worker, with progress and cancellation:
+let cancelled = false;
+self.onmessage = async ({ data }) => {
+    if (data.type === 'cancel') {
+        cancelled = true;
+        return;
+    }
+    await init();
+    cancelled = false;
+    const processor = new ImageProcessor(new CompressionOptions(data.width, data.height));
+    let progress = processor.start(data.imageData);
+    while (!progress.done) {
+        // Let queued 'cancel' messages run between steps
+        await new Promise((resolve) => setTimeout(resolve, 0));
+        if (cancelled) {
+            processor.cancel();
+            return self.postMessage({ type: 'cancelled' });
+        }
+        progress = processor.step(64);
+        self.postMessage({ type: 'progress', progress });
+    }
+    self.postMessage({ type: 'result', result: processor.finish() });
+};
+*/
\ No newline at end of file
@@ -0,0 +1,417 @@
+use crate::error::{Error, Result};
+
+// Deflate length and distance alphabets (RFC 1951, 3.2.5)
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195,
+    227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073,
+    4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// Order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const MAX_BITS: usize = 15;
+const END_OF_BLOCK: usize = 256;
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// Candidates examined per position when looking for a match
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: usize = 15;
+
+// Decompresses a zlib stream (RFC 1950) and checks its Adler-32. Streams that
+// would inflate past `max_length` bytes are rejected before they are expanded.
+pub fn decompress(data: &[u8], max_length: usize) -> Result<Vec<u8>> {
+    if data.len() < 6 {
+        return Err(Error::Decode("zlib stream is truncated".to_string()));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0F != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(Error::Decode("Invalid zlib header".to_string()));
+    }
+    if flg & 0x20 != 0 {
+        return Err(Error::Decode("zlib preset dictionaries are not supported".to_string()));
+    }
+
+    let mut reader = BitReader { data: &data[2..], position: 0, bit_buffer: 0, bit_count: 0 };
+    let output = inflate(&mut reader, max_length)?;
+    let trailer = reader
+        .data
+        .get(reader.position..reader.position + 4)
+        .ok_or_else(|| Error::Decode("zlib checksum is missing".to_string()))?;
+    if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&output) {
+        return Err(Error::Decode("zlib checksum mismatch".to_string()));
+    }
+    Ok(output)
+}
+
+// zlib stream of `data`: LZ77 matches coded with the fixed Huffman tables
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.bytes.extend_from_slice(&[0x78, 0x9C]);
+    // A single final block with fixed codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |head: &mut Vec<usize>, previous: &mut Vec<usize>, position: usize| {
+        if position + MIN_MATCH <= data.len() {
+            let hash = hash(&data[position..position + MIN_MATCH]);
+            previous[position % WINDOW_SIZE] = head[hash];
+            head[hash] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = longest_match(data, position, &head, &previous);
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for offset in 0..length {
+                insert(&mut head, &mut previous, position + offset);
+            }
+            position += length;
+        } else {
+            write_fixed_literal(&mut writer, data[position] as usize);
+            insert(&mut head, &mut previous, position);
+            position += 1;
+        }
+    }
+    write_fixed_literal(&mut writer, END_OF_BLOCK);
+
+    let mut output = writer.finish();
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the longest run that cannot overflow before the reduction
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+fn inflate(reader: &mut BitReader, max_length: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.bits(16)? as usize;
+                if reader.bits(16)? as usize != !length & 0xFFFF {
+                    return Err(Error::Decode("Stored deflate block length is corrupt".to_string()));
+                }
+                let bytes = reader
+                    .data
+                    .get(reader.position..reader.position + length)
+                    .ok_or_else(|| Error::Decode("Stored deflate block is truncated".to_string()))?;
+                reserve(&output, length, max_length)?;
+                output.extend_from_slice(bytes);
+                reader.position += length;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(reader, &mut output, &literals, &distances, max_length)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_block(reader, &mut output, &literals, &distances, max_length)?;
+            }
+            _ => return Err(Error::Decode("Invalid deflate block type".to_string())),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    max_length: usize,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            reserve(output, 1, max_length)?;
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(Error::Decode("Invalid deflate length code".to_string()));
+        }
+        let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as usize)? as usize;
+        let index = distances.decode(reader)?;
+        if index >= DISTANCE_BASE.len() {
+            return Err(Error::Decode("Invalid deflate distance code".to_string()));
+        }
+        let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as usize)? as usize;
+        if distance > output.len() {
+            return Err(Error::Decode("Deflate distance reaches before the output".to_string()));
+        }
+        reserve(output, length, max_length)?;
+        // Byte by byte, since a match may overlap the bytes it produces
+        let start = output.len() - distance;
+        for offset in 0..length {
+            output.push(output[start + offset]);
+        }
+    }
+}
+
+// Fails if `additional` more bytes would take the output past `max_length`
+fn reserve(output: &[u8], additional: usize, max_length: usize) -> Result<()> {
+    if output.len() + additional > max_length {
+        return Err(Error::Decode(format!("Deflate stream inflates past {} bytes", max_length)));
+    }
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| Error::Decode("Deflate repeat code without a previous length".to_string()))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[END_OF_BLOCK] == 0 {
+        return Err(Error::Decode("Invalid deflate code lengths".to_string()));
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+// Canonical Huffman decoder: symbol counts per code length and the symbols in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&symbol| lengths[symbol as usize] > 0).collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Self { counts, symbols }
+    }
+
+    // Reads one bit at a time, comparing against the first code of each length
+    fn decode(&self, reader: &mut BitReader) -> Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::Decode("Invalid deflate Huffman code".to_string()))
+    }
+}
+
+// LSB-first bit reader over deflate data
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: usize) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| Error::Decode("Deflate data is truncated".to_string()))?;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // Drops the rest of the current byte; whole buffered bytes are handed back
+    fn align_to_byte(&mut self) {
+        self.position -= self.bit_count / 8;
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+// LSB-first bit writer; Huffman codes are written MSB-first through `write_code`
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u32,
+    bit_count: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: usize) {
+        self.bit_buffer |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    fn write_code(&mut self, code: u32, length: usize) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn longest_match(data: &[u8], position: usize, head: &[usize], previous: &[usize]) -> (usize, usize) {
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(&data[position..position + MIN_MATCH])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || position - candidate > WINDOW_SIZE {
+            break;
+        }
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = position - candidate;
+            if length == max_length {
+                break;
+            }
+        }
+        let next = previous[candidate % WINDOW_SIZE];
+        // Older entries of the ring buffer may have been overwritten
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+    }
+    (best_length, best_distance)
+}
+
+fn write_fixed_literal(writer: &mut BitWriter, symbol: usize) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+    write_fixed_literal(writer, 257 + index);
+    writer.write((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as usize);
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+    writer.write_code(index as u32, 5);
+    writer.write((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = b"Baseline DCT, baseline DCT, baseline DCT and some literals: 0123456789".repeat(40);
+        let noise: Vec<u8> = (0u32..5000).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        for data in [&text[..], &noise[..], &[][..], &[7; 1000][..]] {
+            let compressed = compress(data);
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+            assert_eq!(decompress(&compressed, data.len().saturating_sub(1)).is_err(), !data.is_empty());
+        }
+        assert!(compress(&text).len() < text.len() / 10);
+
+        // Stored block as written by zlib at level 0
+        let stored = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c', 0x02, 0x4D, 0x01, 0x27];
+        assert_eq!(decompress(&stored, 3).unwrap(), b"abc");
+        let mut corrupt = compress(&text);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(decompress(&corrupt, text.len()).is_err());
+    }
+}
@@ -3,11 +3,108 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use rust_dct::{Encoder, Matrix, PlanarImage};
+use std::f64::consts::PI;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
+#[allow(clippy::eq_op)]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+// The crate's orthonormal DCT-II basis, built the same way
+fn dct_basis(size: usize) -> Matrix {
+    let scale_factor = f64::sqrt(2.0 / size as f64);
+    (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| match i {
+                    0 => scale_factor / f64::sqrt(2.0),
+                    _ => scale_factor * f64::cos((i as f64 * (2 * (j + 1) - 1) as f64 * PI) / (2 * size) as f64),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn transpose(matrix: &Matrix) -> Matrix {
+    (0..matrix[0].len()).map(|j| matrix.iter().map(|row| row[j]).collect()).collect()
+}
+
+// Same fixture as the crate's `matrix_ops::test_image`, which is only built
+// for the library's own unit tests
+fn test_image(width: usize, height: usize) -> Matrix {
+    (0..height)
+        .map(|y| (0..width).map(|x| ((x * 29 + y * 17) % 97 + 64) as f64).collect())
+        .collect()
+}
+
+// Plain scalar product, summing over k in order
+fn multiply(left: &Matrix, right: &Matrix) -> Matrix {
+    let mut result = vec![vec![0.0; right[0].len()]; left.len()];
+    for i in 0..left.len() {
+        for j in 0..right[0].len() {
+            for k in 0..right.len() {
+                result[i][j] += left[i][k] * right[k][j];
+            }
+        }
+    }
+    result
+}
+
+// Built with `+simd128` (see `.cargo/config.toml`), so 8x8 blocks and luma
+// conversion go through the simd128 kernels, which must match scalar loops
+// bit for bit
+#[wasm_bindgen_test]
+fn simd128_kernels_match_scalar_loops() {
+    let mut image = test_image(24, 16);
+    for row in &mut image {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value += (x % 7) as f64 * 0.125;
+        }
+    }
+    let details = Encoder::new().encode(&image).unwrap().details;
+    let basis = dct_basis(8);
+    let basis_transposed = transpose(&basis);
+    let table = &details.quantization_table;
+
+    for (index, block) in details.image_submatrices.iter().enumerate() {
+        let shifted: Matrix = block.iter().map(|row| row.iter().map(|&x| x - 127.0).collect()).collect();
+        let dct = multiply(&multiply(&basis, &shifted), &basis_transposed);
+        assert_eq!(details.dct_matrices[index], dct);
+
+        let quantized: Matrix = dct
+            .iter()
+            .zip(table)
+            .map(|(row, steps)| {
+                row.iter()
+                    .zip(steps)
+                    .map(|(&coefficient, &step)| {
+                        let level = (coefficient / step).round();
+                        (if level == 0.0 { 0.0 } else { level }) * step
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(details.compressed_dct_matrices[index], quantized);
+
+        let reconstructed: Matrix = multiply(&multiply(&basis_transposed, &quantized), &basis)
+            .iter()
+            .map(|row| row.iter().map(|&value| value.round() + 127.0).collect())
+            .collect();
+        assert_eq!(details.compressed_image_submatrices[index], reconstructed);
+    }
+
+    // 37 pixels, so the row ends with a partial vector
+    let planes: Vec<Matrix> = [7, 13, 29]
+        .iter()
+        .map(|&factor| vec![(0..37).map(|x| (x * factor % 256) as f64).collect()])
+        .collect();
+    let luma = PlanarImage::from_planes(planes.clone()).to_luma();
+    let expected: Vec<f64> =
+        (0..37).map(|x| 0.299 * planes[0][0][x] + 0.587 * planes[1][0][x] + 0.114 * planes[2][0][x]).collect();
+    assert_eq!(luma[0], expected);
+}